@@ -1,29 +1,135 @@
 #![allow(dead_code)]
 
+use std::time::Duration;
 use anyhow::{anyhow, bail};
 use zbus::blocking::{Connection, fdo::DBusProxy};
+use zbus::names::OwnedBusName;
 use zbus::Proxy;
+use zbus::proxy::CacheProperties;
 use zbus::blocking::proxy::ProxyImpl;
-use crate::shared::{PlaybackStatus, BASE_PATH};
+use crate::runtime;
+use crate::shared::{ping_all, Discovered, PlaybackStatus, BASE_PATH, DISCOVERY_TIMEOUT};
 
+/// Proxies of all players that answer within [`DISCOVERY_TIMEOUT`].
 pub fn all<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>>
 >(conn: &Connection) -> anyhow::Result<Vec<T>> {
+    Ok(all_with_timeout(conn, DISCOVERY_TIMEOUT)?.proxies)
+}
+
+/// Same as [`all`], but the proxies are built with [`CacheProperties::No`].
+///
+/// Such proxies neither subscribe to `PropertiesChanged` nor fetch the
+/// properties up front, which is cheaper for one-shot commands. Every
+/// property read is a round-trip, and `receive_*_changed` iterators are not
+/// available, use [`upgrade`] to turn a proxy into a cached one.
+pub fn all_uncached<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection) -> anyhow::Result<Vec<T>> {
+    Ok(all_in(conn, BASE_PATH, DISCOVERY_TIMEOUT, CacheProperties::No)?.proxies)
+}
+
+/// Rebuilds `proxy` for the same destination and path with the default
+/// property caching, e.g. to watch a player found with [`all_uncached`].
+pub fn upgrade<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(proxy: &T) -> zbus::Result<T> {
+    let inner = proxy.inner();
+    T::builder(inner.connection())
+        .destination(inner.destination().to_owned())?
+        .path(inner.path().to_owned())?
+        .cache_properties(CacheProperties::default())
+        .build()
+}
+
+/// Same as [`all`], but every player is given at most `timeout` to answer
+/// a `org.freedesktop.DBus.Peer.Ping`.
+///
+/// Players are pinged concurrently, so a single hung player only delays the
+/// result by `timeout` and ends up in [`Discovered::unresponsive`].
+pub fn all_with_timeout<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, timeout: Duration) -> anyhow::Result<Discovered<T>> {
+    all_in(conn, BASE_PATH, timeout, CacheProperties::default())
+}
+
+/// Waits until `name` is owned, or any player appears if [`None`], and
+/// returns the name.
+///
+/// Returns immediately if the name is already owned.
+pub fn wait_for(conn: &Connection, name: Option<&str>) -> zbus::Result<OwnedBusName> {
+    wait_for_in(conn, BASE_PATH, name)
+}
+
+fn wait_for_in(conn: &Connection, namespace: &str, name: Option<&str>) -> zbus::Result<OwnedBusName> {
+    let dbus = DBusProxy::new(conn)?;
+    let matches = |it: &str| match name {
+        Some(name) => it == name,
+        None => it.starts_with(namespace),
+    };
+
+    // Subscribed before listing the names, so a player appearing in between isn't missed
+    let changes = dbus.receive_name_owner_changed()?;
+    if let Some(found) = dbus.list_names()?.into_iter().find(|it| matches(it)) {
+        return Ok(found);
+    }
+    for signal in changes {
+        let args = signal.args()?;
+        if args.new_owner().is_some() && matches(args.name()) {
+            return Ok(args.name().to_owned().into());
+        }
+    }
+    Err(zbus::Error::Failure("Name owner changes ended".to_string()))
+}
+
+fn player_names(conn: &Connection, namespace: &str) -> zbus::Result<Vec<OwnedBusName>> {
     let dbus = DBusProxy::new(conn)?;
     let names = dbus.list_names()?
-        .iter()
-        .filter(|it| it.starts_with(BASE_PATH))
-        .cloned()
-        .collect::<Vec<_>>();
-    let mut proxies: Vec<T> = Vec::new();
-    for name in names {
-        let proxy = T::builder(conn)
-            .destination(name)?
-            .build()?;
-        proxies.push(proxy);
+        .into_iter()
+        .filter(|it| it.starts_with(namespace))
+        .collect();
+    Ok(names)
+}
+
+/// Discovers the players owning a name starting with `namespace`.
+fn all_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, namespace: &str, timeout: Duration, cache: CacheProperties) -> anyhow::Result<Discovered<T>> {
+    let names = player_names(conn, namespace)?;
+    Ok(discover(conn, names, timeout, cache))
+}
+
+fn discover<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, names: Vec<OwnedBusName>, timeout: Duration, cache: CacheProperties) -> Discovered<T> {
+    let (responsive, unresponsive) = runtime::block_on(ping_all(conn.inner(), names, timeout));
+    let mut discovered = Discovered { proxies: Vec::new(), unresponsive };
+    for name in responsive {
+        match build::<T>(conn, name.clone(), cache) {
+            Ok(proxy) => discovered.proxies.push(proxy),
+            Err(e) => {
+                log::debug!("Failed to build proxy for {name}: {e}");
+                discovered.unresponsive.push(name);
+            }
+        }
     }
-    Ok(proxies)
+    discovered
+}
+
+fn build<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, name: OwnedBusName, cache: CacheProperties) -> zbus::Result<T> {
+    T::builder(conn)
+        .destination(name)?
+        .cache_properties(cache)
+        .build()
 }
 
 pub fn by_name<
@@ -41,15 +147,17 @@ pub fn first<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>>
 >(conn: &Connection) -> anyhow::Result<T> {
-    let dbus = DBusProxy::new(conn)?;
-    let Some(name) = dbus.list_names()?
-        .iter().find(|it| it.starts_with(BASE_PATH))
-        .cloned() else {
+    first_in(conn, BASE_PATH)
+}
+
+fn first_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, namespace: &str) -> anyhow::Result<T> {
+    let Some(name) = player_names(conn, namespace)?.into_iter().next() else {
         bail!("No MPRIS2 instances found.");
     };
-    let proxy: T = T::builder(conn)
-        .destination(name)?
-        .build()?;
+    let proxy: T = build(conn, name, CacheProperties::default())?;
 
     Ok(proxy)
 }
@@ -58,7 +166,14 @@ pub fn currently_playing<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>> + Clone
 >(conn: &Connection) -> anyhow::Result<T> {
-    let proxies: Vec<T> = all(conn)?;
+    currently_playing_in(conn, BASE_PATH)
+}
+
+fn currently_playing_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>> + Clone
+>(conn: &Connection, namespace: &str) -> anyhow::Result<T> {
+    let proxies: Vec<T> = all_in(conn, namespace, DISCOVERY_TIMEOUT, CacheProperties::default())?.proxies;
 
     let proxy = proxies.iter()
         .find(|&it| is_playback_status(it.inner(), PlaybackStatus::Playing))
@@ -70,10 +185,10 @@ pub fn currently_playing<
 
 fn is_playback_status(it: &zbus::blocking::Proxy, playback_status: PlaybackStatus) -> bool {
     let result = || {
-        let current_status = PlaybackStatus::from(
-            it.get_property::<String>("PlaybackStatus")?.as_str()
-        );
-        anyhow::Ok(current_status == playback_status)
+        let status = runtime::block_on(
+            runtime::timeout(DISCOVERY_TIMEOUT, it.inner().get_property::<String>("PlaybackStatus"))
+        ).map_err(|_| anyhow!("Timed out reading PlaybackStatus"))??;
+        anyhow::Ok(PlaybackStatus::from(status.as_str()) == playback_status)
     };
     result().unwrap_or(false)
 }
@@ -82,35 +197,81 @@ fn is_playback_status(it: &zbus::blocking::Proxy, playback_status: PlaybackStatu
 #[cfg(test)]
 mod test {
     use log::info;
+    use std::time::Duration;
     use test_log::test;
     use zbus::blocking::Connection;
-    use crate::blocking::MediaPlayer2Proxy;
+    use zbus::names::OwnedBusName;
+    use crate::blocking::{MediaPlayer2Proxy, PlayerProxy};
     use crate::blocking::discovery::*;
+    use crate::runtime;
+    use crate::test_util;
+
+    /// Names of the players of each test start with their own namespace,
+    /// so tests running in parallel don't discover each other's players.
+    fn namespace(test: &str) -> String {
+        format!("{BASE_PATH}zmpris_test.blocking_{test}.")
+    }
+
+    fn serve_named_player(name: &str, playing: bool) -> zbus::Result<zbus::Connection> {
+        runtime::block_on(test_util::serve_named_player(name, playing))
+    }
 
     #[test]
     fn get_all_players() -> anyhow::Result<()> {
+        let namespace = namespace("all");
+        let _player = serve_named_player(&format!("{namespace}player"), false)?;
         let conn = Connection::session()?;
 
-        let _: Vec<MediaPlayer2Proxy> = all(&conn)?;
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, DISCOVERY_TIMEOUT, CacheProperties::default())?;
+        assert_eq!(discovered.proxies.len(), 1);
+        assert_eq!(discovered.proxies[0].identity()?, format!("{namespace}player"));
 
         Ok(())
     }
 
     #[test]
-    fn get_first_player() -> anyhow::Result<()> {
+    fn get_all_players_uncached() -> anyhow::Result<()> {
+        let namespace = namespace("all_uncached");
+        let _player = serve_named_player(&format!("{namespace}player"), false)?;
         let conn = Connection::session()?;
 
-        let result = first::<MediaPlayer2Proxy>(&conn);
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, DISCOVERY_TIMEOUT, CacheProperties::No)?;
+        assert_eq!(discovered.proxies.len(), 1);
+        let proxy = upgrade(&discovered.proxies[0])?;
+        info!("Player identity: {:?}", proxy.identity()?);
 
-        match result {
-            Err(e) => {
-                assert!(e.to_string().eq("No MPRIS2 instances found."));
-            }
-            Ok(proxy) => {
-                info!("Player identity: {:?}", proxy.identity()?);
-            }
-        };
+        Ok(())
+    }
+
+    #[test]
+    fn get_all_players_with_timeout() -> anyhow::Result<()> {
+        let namespace = namespace("all_with_timeout");
+        let hung_name = format!("{namespace}hung");
+        // Never serves any object, so the ping goes unanswered
+        let hung = Connection::session()?;
+        hung.request_name(hung_name.as_str())?;
+        let _player = serve_named_player(&format!("{namespace}player"), false)?;
+        let conn = Connection::session()?;
 
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, Duration::from_millis(200), CacheProperties::default())?;
+        assert_eq!(discovered.unresponsive, [OwnedBusName::try_from(hung_name)?]);
+        assert_eq!(discovered.proxies.len(), 1);
+        assert_eq!(discovered.proxies[0].identity()?, format!("{namespace}player"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_first_player() -> anyhow::Result<()> {
+        let namespace = namespace("first");
+        let conn = Connection::session()?;
+
+        let result = first_in::<MediaPlayer2Proxy>(&conn, &namespace);
+        assert_eq!(result.err().map(|e| e.to_string()).as_deref(), Some("No MPRIS2 instances found."));
+
+        let _player = serve_named_player(&format!("{namespace}player"), false)?;
+        let proxy = first_in::<MediaPlayer2Proxy>(&conn, &namespace)?;
+        assert_eq!(proxy.identity()?, format!("{namespace}player"));
 
         Ok(())
     }
@@ -137,21 +298,41 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn wait_for_name() -> anyhow::Result<()> {
+        let namespace = namespace("wait_for");
+        let name = format!("{namespace}player");
+        let conn = Connection::session()?;
+        let player = Connection::session()?;
+
+        let requested = std::thread::spawn({
+            let name = name.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                player.request_name(name).map(|_| player)
+            }
+        });
+        assert_eq!(wait_for_in(&conn, &namespace, None)?.as_str(), name);
+        let _player = requested.join().expect("Requesting thread panicked")?;
+        // Already owned
+        assert_eq!(wait_for(&conn, Some(&name))?.as_str(), name);
+
+        Ok(())
+    }
+
     #[test]
     fn get_playing() -> anyhow::Result<()> {
+        let namespace = namespace("playing");
         let conn = Connection::session()?;
 
-        let result: anyhow::Result<MediaPlayer2Proxy> = currently_playing(&conn);
+        let result = currently_playing_in::<PlayerProxy>(&conn, &namespace);
+        assert!(result.is_err_and(|e| e.to_string().starts_with("No currently active player found")));
 
-        match result {
-            Err(e) => {
-                assert!(e.to_string().starts_with("No currently active player found"))
-            }
-            Ok(proxy) => {
-                info!("identity={:?}, destination={:?}", proxy.identity()?, proxy.inner().destination());
-            }
-        }
+        let _stopped = serve_named_player(&format!("{namespace}stopped"), false)?;
+        let _playing = serve_named_player(&format!("{namespace}playing"), true)?;
+        let proxy = currently_playing_in::<PlayerProxy>(&conn, &namespace)?;
+        assert_eq!(proxy.inner().destination().as_str(), format!("{namespace}playing"));
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,27 @@
+//! Blocking counterparts of the proxies, player discovery and the
+//! [`Watcher`](watcher::Watcher).
+//!
+//! The higher-level helpers of [`sync`](crate::sync), like the track list
+//! mirror, the playlist cache, the resilient player and following a player,
+//! are async only. They can be driven from blocking code with the
+//! `zbus::blocking` connection's [`inner`](zbus::blocking::Connection::inner)
+//! async connection and an executor.
+
 pub use crate::media_player::MediaPlayer2ProxyBlocking as MediaPlayer2Proxy;
 pub use crate::player::PlayerProxyBlocking as PlayerProxy;
+pub use crate::playlists::PlaylistsProxyBlocking as PlaylistsProxy;
+pub use crate::track_list::TrackListProxyBlocking as TrackListProxy;
+
+// Returned by the `receive_*` methods of the proxies
+pub use crate::player::{Seeked, SeekedArgs, SeekedIterator};
+pub use crate::playlists::{PlaylistChanged, PlaylistChangedArgs, PlaylistChangedIterator};
+pub use crate::track_list::{
+    TrackAdded, TrackAddedArgs, TrackAddedIterator,
+    TrackListReplaced, TrackListReplacedArgs, TrackListReplacedIterator,
+    TrackMetadataChanged, TrackMetadataChangedArgs, TrackMetadataChangedIterator,
+    TrackRemoved, TrackRemovedArgs, TrackRemovedIterator,
+};
+pub use zbus::blocking::proxy::{PropertyChanged, PropertyIterator};
 
-pub mod discovery;
\ No newline at end of file
+pub mod discovery;
+pub mod watcher;
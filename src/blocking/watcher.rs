@@ -0,0 +1,153 @@
+use std::pin::pin;
+use futures::channel::{mpsc, oneshot};
+use futures::future;
+use futures::stream::StreamExt;
+use zbus::blocking::Connection;
+use zbus::names::{BusName, OwnedUniqueName};
+use crate::runtime;
+use crate::shared::PlayerEvent;
+use crate::watcher::{self, Handlers};
+
+/// Watches every media player on the bus through a single set of match rules.
+///
+/// Same as the async watcher, with the signals dispatched on a background
+/// thread. The thread stops and the match rules are removed when the
+/// watcher is dropped.
+pub struct Watcher {
+    conn: Connection,
+    handlers: Handlers,
+    /// Dropped to stop the thread.
+    _stop: oneshot::Sender<()>,
+}
+
+impl Watcher {
+    pub fn new(conn: &Connection) -> zbus::Result<Self> {
+        let (messages, names) = runtime::block_on(async {
+            zbus::Result::Ok((watcher::signals(conn.inner()).await?, watcher::name_counts(conn.inner()).await?))
+        })?;
+        let handlers = Handlers::default();
+        let (stop, stopped) = oneshot::channel();
+        let dispatched = handlers.clone();
+        std::thread::Builder::new()
+            .name("zmpris watcher".to_string())
+            .spawn(move || runtime::block_on(async move {
+                // The streams are dropped on the runtime, which removes their match rules
+                future::select(pin!(watcher::dispatch(messages, dispatched, names)), stopped).await;
+            }))?;
+
+        Ok(Self {
+            conn: conn.clone(),
+            handlers,
+            _stop: stop,
+        })
+    }
+
+    /// Returns an iterator over the events emitted by the player owning `name`.
+    ///
+    /// Well-known names are resolved to their current owner, so the iterator
+    /// ends once that owner holds no MPRIS name anymore (e.g. the player exits).
+    pub fn subscribe<'n, N>(&self, name: N) -> zbus::Result<PlayerEvents>
+    where
+        N: TryInto<BusName<'n>>,
+        N::Error: Into<zbus::Error>,
+    {
+        let name = name.try_into().map_err(Into::into)?;
+        let (owner, receiver) = runtime::block_on(watcher::subscribe(self.conn.inner(), &self.handlers, name))?;
+        Ok(PlayerEvents { owner, receiver })
+    }
+}
+
+/// Iterator over the [`PlayerEvent`]s of a single player, see [`Watcher::subscribe`].
+///
+/// [`next`](Iterator::next) blocks until an event arrives, use
+/// [`try_iter`](Self::try_iter) to poll from an event loop instead.
+#[derive(Debug)]
+pub struct PlayerEvents {
+    owner: OwnedUniqueName,
+    receiver: mpsc::UnboundedReceiver<PlayerEvent>,
+}
+
+impl PlayerEvents {
+    /// Unique name of the player the events come from.
+    pub fn owner(&self) -> &OwnedUniqueName {
+        &self.owner
+    }
+
+    /// Returns the events that already arrived, without blocking.
+    pub fn try_iter(&mut self) -> impl Iterator<Item = PlayerEvent> + '_ {
+        std::iter::from_fn(|| self.receiver.try_next().ok().flatten())
+    }
+}
+
+impl Iterator for PlayerEvents {
+    type Item = PlayerEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        runtime::block_on(self.receiver.next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use futures::StreamExt;
+    use test_log::test;
+    use zbus::blocking::Connection;
+    use zvariant::Value;
+    use crate::blocking::watcher::Watcher;
+    use crate::runtime;
+    use crate::shared::{PlayerEvent, OBJECT_PATH, PLAYER_INTERFACE};
+
+    #[test]
+    fn subscribe_missing_player() -> anyhow::Result<()> {
+        let conn = Connection::session()?;
+        let watcher = Watcher::new(&conn)?;
+
+        let result = watcher.subscribe("org.mpris.MediaPlayer2.com.example.application");
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dispatch_by_sender() -> anyhow::Result<()> {
+        let conn = Connection::session()?;
+        let watcher = Watcher::new(&conn)?;
+        let unique_name = conn.unique_name().expect("No unique name").to_owned();
+        let mut events = watcher.subscribe(unique_name)?;
+        assert_eq!(events.try_iter().next(), None);
+
+        let changed = HashMap::from([("Volume", Value::from(0.5))]);
+        conn.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+        )?;
+        conn.emit_signal(None::<()>, OBJECT_PATH, PLAYER_INTERFACE, "Seeked", &(42i64,))?;
+
+        let Some(PlayerEvent::PropertiesChanged { changed, .. }) = events.next() else {
+            anyhow::bail!("Expected PropertiesChanged");
+        };
+        assert_eq!(f64::try_from(&changed["Volume"])?, 0.5);
+        assert_eq!(events.next(), Some(PlayerEvent::Seeked(42)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stop_on_drop() -> anyhow::Result<()> {
+        let conn = Connection::session()?;
+        let watcher = Watcher::new(&conn)?;
+        let mut events = watcher.subscribe(conn.unique_name().expect("No unique name").to_owned())?;
+
+        // Ends without waiting for another signal
+        drop(watcher);
+        let next = runtime::block_on(runtime::timeout(Duration::from_secs(5), events.receiver.next()));
+        assert_eq!(next, Ok(None));
+
+        Ok(())
+    }
+}
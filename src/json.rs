@@ -0,0 +1,252 @@
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Writes a JSON object member by member.
+#[derive(Debug, Clone)]
+pub(crate) struct ObjectWriter {
+    json: String,
+}
+
+impl ObjectWriter {
+    pub(crate) fn new() -> Self {
+        Self { json: String::from("{") }
+    }
+
+    pub(crate) fn string(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        write_string(&mut self.json, value);
+        self
+    }
+
+    pub(crate) fn number(mut self, key: &str, value: u64) -> Self {
+        self.key(key);
+        let _ = write!(self.json, "{value}");
+        self
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.json.push('}');
+        self.json
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.json.len() > 1 {
+            self.json.push(',');
+        }
+        write_string(&mut self.json, key);
+        self.json.push(':');
+    }
+}
+
+fn write_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// A parsed JSON value, objects keep the order of their members.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(it, _)| it == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The value as an unsigned integer, [`None`] for fractions and other types.
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(number) if number >= 0.0 && number.fract() == 0.0 && number <= u64::MAX as f64 => Some(number as u64),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `input` as a single JSON value, [`None`] if it's malformed.
+pub(crate) fn parse(input: &str) -> Option<Value> {
+    let mut parser = Parser { chars: input.chars().peekable() };
+    let value = parser.value()?;
+    parser.whitespace();
+    parser.chars.peek().is_none().then_some(value)
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Option<Value> {
+        self.whitespace();
+        match *self.chars.peek()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Value::String),
+            't' => self.literal("true", Value::Bool(true)),
+            'f' => self.literal("false", Value::Bool(false)),
+            'n' => self.literal("null", Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn object(&mut self) -> Option<Value> {
+        self.chars.next();
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Some(Value::Object(members));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.chars.next_if_eq(&':')?;
+            members.push((key, self.value()?));
+            self.whitespace();
+            match self.chars.next()? {
+                ',' => {}
+                '}' => return Some(Value::Object(members)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Value> {
+        self.chars.next();
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Some(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            match self.chars.next()? {
+                ',' => {}
+                ']' => return Some(Value::Array(values)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.chars.next_if_eq(&'"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(string),
+                '\\' => match self.chars.next()? {
+                    '"' => string.push('"'),
+                    '\\' => string.push('\\'),
+                    '/' => string.push('/'),
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'u' => string.push(self.escaped_char()?),
+                    _ => return None,
+                },
+                c if c.is_control() => return None,
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which may be a surrogate pair.
+    fn escaped_char(&mut self) -> Option<char> {
+        let high = self.hex()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high);
+        }
+        self.chars.next_if_eq(&'\\')?;
+        self.chars.next_if_eq(&'u')?;
+        let low = self.hex()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+    }
+
+    fn hex(&mut self) -> Option<u32> {
+        (0..4).try_fold(0, |value, _| Some(value << 4 | self.chars.next()?.to_digit(16)?))
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|it| it.is_ascii_digit() || matches!(it, '-' | '+' | '.' | 'e' | 'E')) {
+            number.push(c);
+        }
+        // Stricter than `f64::from_str`, which also takes e.g. `+1`, `.5` and `inf`
+        let digits = number.strip_prefix('-').unwrap_or(&number);
+        let leading_zero = digits.starts_with('0') && digits[1..].starts_with(|it: char| it.is_ascii_digit());
+        if !digits.starts_with(|it: char| it.is_ascii_digit()) || leading_zero || digits.contains(".e") || digits.contains(".E") || digits.ends_with('.') {
+            return None;
+        }
+        number.parse().ok().map(Value::Number)
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Option<Value> {
+        for expected in literal.chars() {
+            self.chars.next_if_eq(&expected)?;
+        }
+        Some(value)
+    }
+
+    fn whitespace(&mut self) {
+        while self.chars.next_if(|it| matches!(it, ' ' | '\t' | '\n' | '\r')).is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::json::{parse, ObjectWriter, Value};
+
+    #[test]
+    fn write_object() {
+        let json = ObjectWriter::new()
+            .string("text", "\"Quoted\"\tTitle\u{1}")
+            .string("key \"quoted\"", "")
+            .number("percentage", 25)
+            .finish();
+        assert_eq!(json, r#"{"text":"\"Quoted\"\tTitle\u0001","key \"quoted\"":"","percentage":25}"#);
+        assert_eq!(parse(&json).and_then(|it| it.get("text").cloned()), Some(Value::String("\"Quoted\"\tTitle\u{1}".to_string())));
+        assert_eq!(ObjectWriter::new().finish(), "{}");
+    }
+
+    #[test]
+    fn parse_values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "\u00e9\ud83c\udfb5\/"}} "#).expect("Not parsed");
+        assert_eq!(value.get("a"), Some(&Value::Array(vec![Value::Number(1.0), Value::Number(-25.0), Value::Bool(true), Value::Null])));
+        assert_eq!(value.get("b").and_then(|it| it.get("c")), Some(&Value::String("é🎵/".to_string())));
+        assert_eq!(Value::Number(3.0).as_u64(), Some(3));
+        assert_eq!(Value::Number(-3.0).as_u64(), None);
+
+        for malformed in ["", "{", r#"{"a" 1}"#, "[1,]", "01", "+1", ".5", "1.", "\"\\x\"", "\"\\ud83c\"", "[] []", "nul"] {
+            assert_eq!(parse(malformed), None, "{malformed}");
+        }
+    }
+}
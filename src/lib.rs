@@ -1,8 +1,27 @@
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+compile_error!("Either the `tokio` or the `async-io` feature has to be enabled");
+
 mod media_player;
 mod player;
+mod runtime;
 
+#[cfg(feature = "sync")]
 pub mod sync;
+#[cfg(feature = "blocking")]
 pub mod blocking;
 pub mod shared;
+pub mod playlist_file;
+pub mod playlist_icon;
+pub mod server;
+#[cfg(feature = "sync")]
+pub mod status_bar;
+#[cfg(feature = "sync")]
+mod json;
 mod playlists;
 mod track_list;
+mod uri;
+#[cfg(any(feature = "sync", feature = "blocking"))]
+mod watcher;
+#[cfg(all(test, any(feature = "sync", feature = "blocking")))]
+#[cfg_attr(not(feature = "sync"), allow(dead_code))]
+mod test_util;
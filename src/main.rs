@@ -0,0 +1,296 @@
+use std::io::BufRead;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::Connection;
+use zmpris::server::daemon::ProxyDaemon;
+use zmpris::shared::BASE_PATH;
+use zmpris::status_bar::{self, parse_i3bar_click, BarProtocol, BarStatus, ClickAction, I3BAR_HEADER};
+use zmpris::sync::follow::{Follow, FollowEvent};
+use zmpris::sync::{discovery, PlayerProxy};
+
+const USAGE: &str = "\
+Usage: zmpris <command> [options]
+
+Commands:
+  daemon          Re-export the last used player as org.mpris.MediaPlayer2.zmpris
+  follow          Print a line whenever the status, track or volume of a player changes
+  bar             Print the status of a player as JSON for waybar or i3bar
+  click <button>  Toggle playback (1), go to the previous (2) or the next track (3)
+
+Options:
+  --player <name>      Use this player instead of the playing or first one
+  --format <template>  Print the line from a template with the placeholders {player},
+                       {status}, {artist}, {title}, {album}, {volume}, {position}, {length}
+  --tooltip <template> Tooltip of the bar, from a template like --format
+  --protocol <name>    JSON protocol of the bar, waybar (default) or i3bar
+  --position           Follow: also print the position every second while playing
+  --reconnect          Follow: wait for another player instead of exiting when the player vanishes";
+
+const FOLLOW_FORMAT: &str = "{status}\t{artist} - {title}";
+const FOLLOW_POSITION_FORMAT: &str = "{status}\t{artist} - {title}\t{position}/{length}";
+const BAR_FORMAT: &str = "{artist} - {title}";
+const BAR_TOOLTIP: &str = "{player}: {status}\n{album}\n{position}/{length}";
+/// Wait before following a player again that couldn't be read.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Options {
+    player: Option<String>,
+    format: Option<String>,
+    tooltip: Option<String>,
+    protocol: Option<BarProtocol>,
+    position: bool,
+    reconnect: bool,
+}
+
+fn main() -> ExitCode {
+    #[cfg(feature = "tokio")]
+    let code = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the tokio runtime")
+        .block_on(run());
+    #[cfg(not(feature = "tokio"))]
+    let code = async_io::block_on(run());
+    code
+}
+
+async fn run() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = match args.first().map(String::as_str) {
+        Some("daemon") => parse_options(&args[1..]).map(|_| Command::Daemon),
+        Some("follow") => parse_options(&args[1..]).map(Command::Follow),
+        Some("bar") => parse_options(&args[1..]).map(Command::Bar),
+        Some("click") => match args.get(1).and_then(|it| it.parse().ok()).and_then(ClickAction::from_button) {
+            Some(action) => parse_options(&args[2..]).map(|options| Command::Click(action, options)),
+            None => Err("click needs a button of 1, 2 or 3".to_string()),
+        },
+        _ => Err("Unknown command".to_string()),
+    };
+    let command = match parsed {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("zmpris: {e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match command {
+        Command::Daemon => daemon().await,
+        Command::Follow(options) => follow(options).await,
+        Command::Bar(options) => bar(options).await,
+        Command::Click(action, options) => click(action, options).await,
+    };
+    if let Err(e) = result {
+        eprintln!("zmpris: {e:#}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+enum Command {
+    Daemon,
+    Follow(Options),
+    Bar(Options),
+    Click(ClickAction, Options),
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--player" => options.player = Some(args.next().ok_or("--player needs a name")?.clone()),
+            "--format" => options.format = Some(args.next().ok_or("--format needs a template")?.clone()),
+            "--tooltip" => options.tooltip = Some(args.next().ok_or("--tooltip needs a template")?.clone()),
+            "--protocol" => {
+                let name = args.next().ok_or("--protocol needs a name")?;
+                options.protocol = Some(BarProtocol::from_name(name).ok_or(format!("Unknown protocol {name}"))?);
+            }
+            "--position" => options.position = true,
+            "--reconnect" => options.reconnect = true,
+            other => return Err(format!("Unknown option {other}")),
+        }
+    }
+    Ok(options)
+}
+
+async fn daemon() -> anyhow::Result<()> {
+    let conn = Connection::session().await?;
+    let _daemon = ProxyDaemon::new(&conn).await?;
+    std::future::pending().await
+}
+
+async fn follow(options: Options) -> anyhow::Result<()> {
+    let conn = Connection::session().await?;
+    let name = player_name(options.player);
+    let template = options.format.as_deref().unwrap_or(match options.position {
+        true => FOLLOW_POSITION_FORMAT,
+        false => FOLLOW_FORMAT,
+    });
+    let tick = options.position.then_some(Duration::from_secs(1));
+
+    loop {
+        let proxy = match select_player(&conn, name.as_deref()).await? {
+            Some(proxy) => proxy,
+            None if options.reconnect => {
+                discovery::wait_for(&conn, name.as_deref()).await?;
+                continue;
+            }
+            None => bail!("No player found"),
+        };
+
+        match print_changes(&proxy, tick, template).await {
+            Ok(()) if !options.reconnect => return Ok(()),
+            Ok(()) => {}
+            // Mostly the player leaving before it could be read, same as it vanishing
+            Err(e) if options.reconnect => {
+                eprintln!("zmpris: {e:#}");
+                sleep(RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Prints a line for every change of the player until it vanishes.
+async fn print_changes(proxy: &PlayerProxy<'_>, tick: Option<Duration>, template: &str) -> anyhow::Result<()> {
+    let mut follow = Follow::new(proxy, tick).await?;
+    while let Some(event) = follow.next_event().await {
+        match event? {
+            FollowEvent::Changed(status) => println!("{}", status_bar::format(&status, template)),
+            FollowEvent::Vanished => break,
+        }
+    }
+    Ok(())
+}
+
+async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(not(feature = "tokio"))]
+    async_io::Timer::after(duration).await;
+}
+
+/// Prints the status until the connection is closed, waiting for a player
+/// whenever there is none.
+async fn bar(options: Options) -> anyhow::Result<()> {
+    let conn = Connection::session().await?;
+    let name = player_name(options.player);
+    let text = options.format.as_deref().unwrap_or(BAR_FORMAT);
+    let tooltip = options.tooltip.as_deref().unwrap_or(BAR_TOOLTIP);
+    let protocol = options.protocol.unwrap_or(BarProtocol::Waybar);
+
+    // `sender` is kept, so the clicks never end even without i3bar sending any
+    let (sender, mut clicks) = mpsc::unbounded();
+    if protocol == BarProtocol::I3bar {
+        println!("{I3BAR_HEADER}\n[");
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Some(button) = parse_i3bar_click(&line) {
+                    let _ = sender.unbounded_send(button);
+                }
+            }
+        });
+    }
+    let mut printed = false;
+    let mut print = |status: &BarStatus| {
+        match protocol {
+            BarProtocol::Waybar => println!("{}", status.to_waybar()),
+            BarProtocol::I3bar => println!("{}[{}]", if printed { "," } else { "" }, status.to_i3bar("zmpris")),
+        }
+        printed = true;
+    };
+
+    loop {
+        let Some(proxy) = select_player(&conn, name.as_deref()).await? else {
+            print(&BarStatus::empty());
+            discovery::wait_for(&conn, name.as_deref()).await?;
+            continue;
+        };
+        // Clicks without a player don't apply to the next one
+        while let Ok(Some(_)) = clicks.try_next() {}
+
+        // Shown as no player, so the module stays up when the player left meanwhile
+        let mut follow = match Follow::new(&proxy, Some(Duration::from_secs(1))).await {
+            Ok(follow) => follow,
+            Err(e) => {
+                eprintln!("zmpris: {e}");
+                print(&BarStatus::empty());
+                sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        loop {
+            let next = match future::select(pin!(follow.next_event()), clicks.next()).await {
+                Either::Left((event, _)) => Either::Left(event),
+                Either::Right((button, _)) => Either::Right(button),
+            };
+            match next {
+                Either::Left(None) => return Ok(()),
+                Either::Left(Some(Ok(FollowEvent::Changed(status)))) => print(&BarStatus::new(&status, text, tooltip)),
+                Either::Left(Some(Ok(FollowEvent::Vanished))) => break,
+                Either::Left(Some(Err(e))) => {
+                    eprintln!("zmpris: {e}");
+                    print(&BarStatus::empty());
+                    sleep(RETRY_DELAY).await;
+                    break;
+                }
+                Either::Right(button) => {
+                    let Some(action) = button.and_then(ClickAction::from_button) else {
+                        continue;
+                    };
+                    if let Err(e) = action.apply(follow.proxy()).await {
+                        eprintln!("zmpris: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn click(action: ClickAction, options: Options) -> anyhow::Result<()> {
+    let conn = Connection::session().await?;
+    let name = player_name(options.player);
+    let proxy = select_player(&conn, name.as_deref()).await?.ok_or(anyhow!("No player found"))?;
+    action.apply(&proxy).await?;
+    Ok(())
+}
+
+/// `name` as a bus name, which may be given without the MPRIS prefix.
+fn player_name(name: Option<String>) -> Option<String> {
+    name.map(|it| match it.starts_with(BASE_PATH) {
+        true => it,
+        false => format!("{BASE_PATH}{it}"),
+    })
+}
+
+/// The player called `name`, or else the playing or the first one.
+async fn select_player(conn: &Connection, name: Option<&str>) -> anyhow::Result<Option<PlayerProxy<'static>>> {
+    let Some(name) = name else {
+        if let Ok(proxy) = discovery::currently_playing::<PlayerProxy>(conn).await {
+            return Ok(Some(proxy));
+        }
+        return Ok(discovery::first::<PlayerProxy>(conn).await.ok());
+    };
+
+    let dbus = DBusProxy::new(conn).await?;
+    if !dbus.name_has_owner(BusName::try_from(name)?).await? {
+        return Ok(None);
+    }
+    let proxy = PlayerProxy::builder(conn)
+        .destination(name.to_string())?
+        .build().await?;
+    Ok(Some(proxy))
+}
@@ -33,7 +33,7 @@ pub trait Player {
     fn previous(&self) -> zbus::Result<()>;
 
     /// Pauses playback.
-
+    ///
     /// If playback is already paused, this has no effect.
     ///
     ///   Calling Play after this should cause playback to start again
@@ -231,7 +231,7 @@ pub trait Player {
     #[zbus(property)]
     fn metadata(
         &self,
-    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::Value>>;
+    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::Value<'_>>>;
 
     /// he volume level.
     ///
@@ -407,7 +407,7 @@ pub trait Player {
     fn seeked(&self, position: TimeInUs) -> zbus::Result<()>;
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sync"))]
 mod test {
     use crate::sync::{MediaPlayer2Proxy, PlayerProxy};
     use anyhow::{bail, Ok};
@@ -0,0 +1,467 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::shared::{Metadata, TimeInUs};
+use crate::uri::{file_uri, percent_decode};
+
+pub use crate::uri::scheme;
+
+/// A playlist file format.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum PlaylistFormat {
+    /// Extended M3U, with `#EXTINF` lines.
+    M3u,
+    /// [XML Shareable Playlist Format](https://xspf.org/spec).
+    Xspf,
+    /// PLS, as used by Winamp and SHOUTcast.
+    Pls,
+}
+
+impl PlaylistFormat {
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "xspf" => Some(Self::Xspf),
+            "pls" => Some(Self::Pls),
+            _ => None,
+        }
+    }
+
+    /// The usual file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::M3u => "m3u",
+            Self::Xspf => "xspf",
+            Self::Pls => "pls",
+        }
+    }
+}
+
+/// A single entry of a playlist file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlaylistEntry {
+    /// URI or path of the media.
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Vec<String>,
+    pub album: Option<String>,
+    /// Duration in microseconds.
+    pub duration: Option<TimeInUs>,
+}
+
+impl PlaylistEntry {
+    /// Builds an entry from track metadata, [`None`] if it has no `xesam:url`.
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        Some(Self {
+            location: metadata.url.clone()?,
+            title: metadata.title.clone(),
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            duration: metadata.length,
+        })
+    }
+
+    /// `Artist - Title` as commonly displayed by players.
+    fn display_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        if self.artist.is_empty() {
+            Some(title.to_string())
+        } else {
+            Some(format!("{} - {title}", self.artist.join(", ")))
+        }
+    }
+
+    fn duration_secs(&self) -> i64 {
+        self.duration.map_or(-1, |it| it / 1_000_000)
+    }
+}
+
+/// Writes `entries` to `writer` in the given format.
+pub fn write(format: PlaylistFormat, entries: &[PlaylistEntry], writer: impl Write) -> io::Result<()> {
+    match format {
+        PlaylistFormat::M3u => write_m3u(entries, writer),
+        PlaylistFormat::Xspf => write_xspf(entries, writer),
+        PlaylistFormat::Pls => write_pls(entries, writer),
+    }
+}
+
+fn write_m3u(entries: &[PlaylistEntry], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "#EXTM3U")?;
+    for entry in entries {
+        writeln!(writer, "#EXTINF:{},{}", entry.duration_secs(), entry.display_title().unwrap_or_default())?;
+        writeln!(writer, "{}", entry.location)?;
+    }
+    Ok(())
+}
+
+fn write_xspf(entries: &[PlaylistEntry], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#)?;
+    writeln!(writer, "  <trackList>")?;
+    for entry in entries {
+        writeln!(writer, "    <track>")?;
+        writeln!(writer, "      <location>{}</location>", escape_xml(&entry.location))?;
+        if let Some(title) = &entry.title {
+            writeln!(writer, "      <title>{}</title>", escape_xml(title))?;
+        }
+        if !entry.artist.is_empty() {
+            writeln!(writer, "      <creator>{}</creator>", escape_xml(&entry.artist.join(", ")))?;
+        }
+        if let Some(album) = &entry.album {
+            writeln!(writer, "      <album>{}</album>", escape_xml(album))?;
+        }
+        if let Some(duration) = entry.duration {
+            writeln!(writer, "      <duration>{}</duration>", duration / 1_000)?;
+        }
+        writeln!(writer, "    </track>")?;
+    }
+    writeln!(writer, "  </trackList>")?;
+    writeln!(writer, "</playlist>")
+}
+
+fn write_pls(entries: &[PlaylistEntry], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "[playlist]")?;
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        writeln!(writer, "File{number}={}", entry.location)?;
+        if let Some(title) = entry.display_title() {
+            writeln!(writer, "Title{number}={title}")?;
+        }
+        writeln!(writer, "Length{number}={}", entry.duration_secs())?;
+    }
+    writeln!(writer, "NumberOfEntries={}", entries.len())?;
+    writeln!(writer, "Version=2")
+}
+
+/// Parses a playlist in the given format.
+///
+/// Locations that are not URIs are treated as paths and turned into
+/// `file://` URIs, relative ones are resolved against `base`, which should
+/// be the directory containing the playlist file. In XSPF such locations are
+/// relative URIs, so they are percent-decoded first.
+pub fn parse(format: PlaylistFormat, input: &str, base: Option<&Path>) -> Vec<PlaylistEntry> {
+    let input = input.trim_start_matches('\u{feff}');
+    let mut entries = match format {
+        PlaylistFormat::M3u => parse_m3u(input),
+        PlaylistFormat::Xspf => parse_xspf(input),
+        PlaylistFormat::Pls => parse_pls(input),
+    };
+    for entry in &mut entries {
+        // XSPF locations are URI references, so relative ones are percent-encoded
+        if format == PlaylistFormat::Xspf && scheme(&entry.location).is_none() {
+            entry.location = String::from_utf8_lossy(&percent_decode(&entry.location)).into_owned();
+        }
+        entry.location = resolve(&entry.location, base);
+    }
+    entries
+}
+
+/// Reads and parses the playlist file at `path`, guessing the format from its extension.
+///
+/// Files that aren't UTF-8 are read as Latin-1. See [`parse`].
+pub fn parse_file(path: &Path) -> io::Result<Vec<PlaylistEntry>> {
+    let format = PlaylistFormat::from_path(path).ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Unknown playlist format: {}", path.display()),
+    ))?;
+    let input = read_text(path)?;
+    let base = std::path::absolute(path)?.parent().map(Path::to_path_buf);
+    Ok(parse(format, &input, base.as_deref()))
+}
+
+/// Reads the text file at `path`, decoding it as Latin-1 if it isn't UTF-8,
+/// as older M3U and PLS files often are.
+pub(crate) fn read_text(path: &Path) -> io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(String::from_utf8(bytes).unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect()))
+}
+
+/// The title of the playlist itself, from `#PLAYLIST:` in M3U or the
+/// playlist's `<title>` in XSPF. PLS has no title.
+pub fn parse_title(format: PlaylistFormat, input: &str) -> Option<String> {
+    let title = match format {
+        PlaylistFormat::M3u => input.lines()
+            .find_map(|it| it.trim().strip_prefix("#PLAYLIST:"))
+            .map(String::from),
+        // Only a title before the tracks belongs to the playlist
+        PlaylistFormat::Xspf => element(input.split("<trackList").next()?, "title")
+            .map(|(it, _)| unescape_xml(it)),
+        PlaylistFormat::Pls => None,
+    };
+    title.map(|it| it.trim().to_string()).filter(|it| !it.is_empty())
+}
+
+fn resolve(location: &str, base: Option<&Path>) -> String {
+    if scheme(location).is_some() {
+        return location.to_string();
+    }
+    let path = Path::new(location);
+    let path = match base {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => PathBuf::from(path),
+    };
+    file_uri(&path)
+}
+
+fn parse_m3u(input: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending = PlaylistEntry::default();
+    for line in input.lines().map(str::trim).filter(|it| !it.is_empty()) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = info.split_once(',').unwrap_or((info, ""));
+            pending.duration = duration_from_secs(duration);
+            pending.title = Some(title.trim().to_string()).filter(|it| !it.is_empty());
+        } else if !line.starts_with('#') {
+            pending.location = line.to_string();
+            entries.push(std::mem::take(&mut pending));
+        }
+    }
+    entries
+}
+
+fn parse_pls(input: &str) -> Vec<PlaylistEntry> {
+    let mut entries: BTreeMap<u32, PlaylistEntry> = BTreeMap::new();
+    for line in input.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let split = key.find(|it: char| it.is_ascii_digit()).unwrap_or(key.len());
+        let (key, number) = key.split_at(split);
+        let Ok(number) = number.parse() else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match key.to_ascii_lowercase().as_str() {
+            "file" => entry.location = value.to_string(),
+            "title" => entry.title = Some(value.to_string()),
+            "length" => entry.duration = duration_from_secs(value),
+            _ => {}
+        }
+    }
+    entries.into_values().filter(|it| !it.location.is_empty()).collect()
+}
+
+fn parse_xspf(input: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut rest = input;
+    while let Some((track, after)) = element(rest, "track") {
+        rest = after;
+        let Some((location, _)) = element(track, "location") else {
+            continue;
+        };
+        entries.push(PlaylistEntry {
+            location: unescape_xml(location.trim()),
+            title: element(track, "title").map(|(it, _)| unescape_xml(it.trim())),
+            artist: element(track, "creator").map(|(it, _)| vec![unescape_xml(it.trim())]).unwrap_or_default(),
+            album: element(track, "album").map(|(it, _)| unescape_xml(it.trim())),
+            duration: element(track, "duration")
+                .and_then(|(it, _)| it.trim().parse::<TimeInUs>().ok())
+                .map(|it| it * 1_000),
+        });
+    }
+    entries
+}
+
+/// Content of the first `<name>` element in `input` and the input following it.
+fn element<'a>(input: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let open = format!("<{name}");
+    let close = format!("</{name}>");
+    let mut start = 0;
+    loop {
+        let found = start + input[start..].find(&open)?;
+        let after_name = &input[found + open.len()..];
+        // Skip elements that only share the prefix, like `<trackList>` for `<track`
+        if after_name.starts_with(['>', ' ', '\t', '\n', '\r']) {
+            let content_start = found + open.len() + after_name.find('>')? + 1;
+            let content_end = content_start + input[content_start..].find(&close)?;
+            return Some((&input[content_start..content_end], &input[content_end + close.len()..]));
+        }
+        start = found + open.len();
+    }
+}
+
+fn duration_from_secs(value: &str) -> Option<TimeInUs> {
+    let secs: i64 = value.trim().parse().ok()?;
+    (secs >= 0).then_some(secs * 1_000_000)
+}
+
+fn unescape_xml(value: &str) -> String {
+    value.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::playlist_file::{parse, parse_file, parse_title, scheme, write, PlaylistEntry, PlaylistFormat};
+    use test_log::test;
+
+    fn entries() -> Vec<PlaylistEntry> {
+        vec![
+            PlaylistEntry {
+                location: "file:///music/a.flac".to_string(),
+                title: Some("Rock & Roll".to_string()),
+                artist: vec!["First".to_string(), "Second".to_string()],
+                album: None,
+                duration: Some(185_500_000),
+            },
+            PlaylistEntry {
+                location: "http://example.com/stream".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test(tokio::test)]
+    async fn write_m3u() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write(PlaylistFormat::M3u, &entries(), &mut output)?;
+
+        assert_eq!(String::from_utf8(output)?, "#EXTM3U\n\
+            #EXTINF:185,First, Second - Rock & Roll\n\
+            file:///music/a.flac\n\
+            #EXTINF:-1,\n\
+            http://example.com/stream\n");
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn write_xspf() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write(PlaylistFormat::Xspf, &entries(), &mut output)?;
+        let output = String::from_utf8(output)?;
+
+        assert!(output.contains("<title>Rock &amp; Roll</title>"));
+        assert!(output.contains("<creator>First, Second</creator>"));
+        assert!(output.contains("<duration>185500</duration>"));
+        assert!(output.contains("<location>http://example.com/stream</location>"));
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn write_pls() -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        write(PlaylistFormat::Pls, &entries(), &mut output)?;
+
+        assert_eq!(String::from_utf8(output)?, "[playlist]\n\
+            File1=file:///music/a.flac\n\
+            Title1=First, Second - Rock & Roll\n\
+            Length1=185\n\
+            File2=http://example.com/stream\n\
+            Length2=-1\n\
+            NumberOfEntries=2\n\
+            Version=2\n");
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn round_trip() -> anyhow::Result<()> {
+        for format in [PlaylistFormat::M3u, PlaylistFormat::Xspf, PlaylistFormat::Pls] {
+            let mut output = Vec::new();
+            write(format, &entries(), &mut output)?;
+
+            let parsed = parse(format, &String::from_utf8(output)?, None);
+            let locations: Vec<_> = parsed.iter().map(|it| it.location.as_str()).collect();
+            assert_eq!(locations, ["file:///music/a.flac", "http://example.com/stream"], "{format:?}");
+            // Only XSPF stores milliseconds
+            let duration = if format == PlaylistFormat::Xspf { 185_500_000 } else { 185_000_000 };
+            assert_eq!(parsed[0].duration, Some(duration), "{format:?}");
+        }
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn resolve_relative_paths() -> anyhow::Result<()> {
+        let input = "#EXTM3U\n\
+            #EXTINF:-1,Relative\n\
+            album/01 track.mp3\n\
+            /absolute/track.mp3\n\
+            # comment\n\
+            https://example.com/stream.ogg\n";
+
+        let parsed = parse(PlaylistFormat::M3u, input, Some(Path::new("/home/user/Music")));
+        let locations: Vec<_> = parsed.iter().map(|it| it.location.as_str()).collect();
+        assert_eq!(locations, [
+            "file:///home/user/Music/album/01%20track.mp3",
+            "file:///absolute/track.mp3",
+            "https://example.com/stream.ogg",
+        ]);
+        assert_eq!(parsed[0].title.as_deref(), Some("Relative"));
+        assert_eq!(parsed[0].duration, None);
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn resolve_relative_xspf_locations() -> anyhow::Result<()> {
+        let input = "<playlist><trackList>\
+            <track><location>album/01%20track.mp3</location></track>\
+            <track><location>file:///music/a%20b.mp3</location></track>\
+            </trackList></playlist>";
+
+        let parsed = parse(PlaylistFormat::Xspf, input, Some(Path::new("/home/user/Music")));
+        let locations: Vec<_> = parsed.iter().map(|it| it.location.as_str()).collect();
+        assert_eq!(locations, ["file:///home/user/Music/album/01%20track.mp3", "file:///music/a%20b.mp3"]);
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn parse_latin1_file() -> anyhow::Result<()> {
+        let directory = std::env::temp_dir().join(format!("zmpris-latin1-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join("latin1.m3u");
+        std::fs::write(&path, b"#EXTM3U\n#EXTINF:10,Caf\xe9\nhttp://example.com/a\n")?;
+
+        let parsed = parse_file(&path);
+        std::fs::remove_dir_all(&directory)?;
+        assert_eq!(parsed?[0].title.as_deref(), Some("Café"));
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn uri_scheme() -> anyhow::Result<()> {
+        assert_eq!(scheme("file:///a.mp3"), Some("file"));
+        assert_eq!(scheme("rtsp://example.com"), Some("rtsp"));
+        assert_eq!(scheme("C:\\Music\\a.mp3"), None);
+        assert_eq!(scheme("music/a.mp3"), None);
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn playlist_title() -> anyhow::Result<()> {
+        assert_eq!(parse_title(PlaylistFormat::M3u, "#EXTM3U\n#PLAYLIST: Road Trip \na.mp3\n").as_deref(), Some("Road Trip"));
+        assert_eq!(parse_title(PlaylistFormat::M3u, "#EXTM3U\na.mp3\n"), None);
+        let xspf = "<playlist><title>Rock &amp; Roll</title><trackList><track><title>Track</title></track></trackList></playlist>";
+        assert_eq!(parse_title(PlaylistFormat::Xspf, xspf).as_deref(), Some("Rock & Roll"));
+        assert_eq!(parse_title(PlaylistFormat::Xspf, "<playlist><trackList><track><title>Track</title></track></trackList></playlist>"), None);
+
+        anyhow::Ok(())
+    }
+}
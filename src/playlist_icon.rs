@@ -0,0 +1,389 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::shared::Playlist;
+use crate::uri::{decode_data_uri, file_path, scheme};
+
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+const FALLBACK_THEME: &str = "hicolor";
+const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// Image data of a resolved icon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icon {
+    pub data: Vec<u8>,
+    /// MIME type, from the data URI or guessed from the file extension.
+    pub mime_type: Option<String>,
+}
+
+/// Resolves playlist icons to image data.
+///
+/// Supported are `file://` URIs of the local host, `data:` URIs and, as a
+/// fallback for players sending them, [freedesktop icon theme](https://specifications.freedesktop.org/icon-theme-spec/latest/)
+/// names. Plain paths are not read, the player may run on another machine.
+///
+/// Results of file and theme lookups, including failed ones, are cached
+/// until [`clear_cache`](Self::clear_cache) is called. Once the cache is
+/// full, the oldest results are forgotten first.
+pub struct IconResolver {
+    theme: Option<String>,
+    size: u32,
+    search_paths: Vec<PathBuf>,
+    cache: Mutex<IconCache>,
+}
+
+impl Default for IconResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IconResolver {
+    /// Creates a resolver looking up theme icons of size 48 in the `hicolor`
+    /// theme and the XDG base directories.
+    pub fn new() -> Self {
+        Self {
+            theme: None,
+            size: 48,
+            search_paths: default_search_paths(),
+            cache: Mutex::new(IconCache::new(DEFAULT_CACHE_CAPACITY)),
+        }
+    }
+
+    /// Icon theme to look up names in before `hicolor`.
+    pub fn with_theme(mut self, theme: impl Into<String>) -> Self {
+        self.theme = Some(theme.into());
+        self
+    }
+
+    /// Preferred size of theme icons, in pixels.
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Directories containing icon themes and unthemed icons, in lookup order.
+    pub fn with_search_paths(mut self, search_paths: Vec<PathBuf>) -> Self {
+        self.search_paths = search_paths;
+        self
+    }
+
+    /// Number of results kept in the cache, 256 by default.
+    pub fn with_cache_capacity(self, capacity: usize) -> Self {
+        Self { cache: Mutex::new(IconCache::new(capacity)), ..self }
+    }
+
+    /// Resolves the icon of `playlist`, [`None`] if it has none or it can't be read.
+    pub fn resolve_playlist(&self, playlist: &Playlist) -> Option<Arc<Icon>> {
+        self.resolve(playlist.uri())
+    }
+
+    /// Resolves `uri` to image data, [`None`] if it is empty or can't be read.
+    pub fn resolve(&self, uri: &str) -> Option<Arc<Icon>> {
+        if uri.is_empty() {
+            return None;
+        }
+        // Decoding is cheap and the URIs may be large, so they aren't cached
+        if let Some((mime_type, data)) = decode_data_uri(uri) {
+            return Some(Arc::new(Icon { data, mime_type }));
+        }
+        if let Some(icon) = self.cache.lock().expect("Icon cache lock poisoned").get(uri) {
+            return icon;
+        }
+
+        let icon = self.load(uri).map(Arc::new);
+        if icon.is_none() {
+            log::debug!("Failed to resolve icon {uri}");
+        }
+        self.cache.lock().expect("Icon cache lock poisoned").insert(uri.to_string(), icon.clone());
+        icon
+    }
+
+    /// Forgets all resolved icons.
+    pub fn clear_cache(&self) {
+        self.cache.lock().expect("Icon cache lock poisoned").clear();
+    }
+
+    fn load(&self, uri: &str) -> Option<Icon> {
+        match scheme(uri) {
+            Some(_) => read_file(&file_path(uri)?),
+            None => read_file(&self.lookup(uri)?),
+        }
+    }
+
+    /// Looks up the icon called `name` in the configured theme, the themes
+    /// it inherits from, `hicolor` and finally unthemed icons.
+    fn lookup(&self, name: &str) -> Option<PathBuf> {
+        // Names are file names within the themes, not paths
+        if name.contains('/') || name.starts_with('.') {
+            return None;
+        }
+        let mut themes: Vec<String> = self.theme.iter().cloned().collect();
+        themes.push(FALLBACK_THEME.to_string());
+
+        let mut visited = Vec::new();
+        while !themes.is_empty() {
+            let theme = themes.remove(0);
+            if visited.contains(&theme) {
+                continue;
+            }
+            visited.push(theme.clone());
+
+            let Some(index) = self.theme_index(&theme) else {
+                continue;
+            };
+            if let Some(path) = self.lookup_in_theme(&theme, &index, name) {
+                return Some(path);
+            }
+            // Inherited themes come before the remaining fallbacks
+            themes.splice(0..0, index.inherits);
+        }
+
+        self.search_paths.iter()
+            .flat_map(|base| ICON_EXTENSIONS.iter().map(move |it| base.join(format!("{name}.{it}"))))
+            .find(|it| it.is_file())
+    }
+
+    fn theme_index(&self, theme: &str) -> Option<ThemeIndex> {
+        self.search_paths.iter()
+            .find_map(|base| std::fs::read_to_string(base.join(theme).join("index.theme")).ok())
+            .map(|it| ThemeIndex::parse(&it))
+    }
+
+    fn lookup_in_theme(&self, theme: &str, index: &ThemeIndex, name: &str) -> Option<PathBuf> {
+        let mut directories: Vec<&ThemeDirectory> = index.directories.iter().collect();
+        directories.sort_by_key(|it| it.distance(self.size));
+
+        directories.iter()
+            .flat_map(|directory| self.search_paths.iter().map(move |base| base.join(theme).join(&directory.path)))
+            .flat_map(|directory| ICON_EXTENSIONS.iter().map(move |it| directory.join(format!("{name}.{it}"))))
+            .find(|it| it.is_file())
+    }
+}
+
+/// Resolved icons by URI, forgetting the oldest beyond the capacity.
+struct IconCache {
+    capacity: usize,
+    icons: HashMap<String, Option<Arc<Icon>>>,
+    order: VecDeque<String>,
+}
+
+impl IconCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, icons: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// The cached result for `uri`, [`None`] if there is none.
+    fn get(&self, uri: &str) -> Option<Option<Arc<Icon>>> {
+        self.icons.get(uri).cloned()
+    }
+
+    fn insert(&mut self, uri: String, icon: Option<Arc<Icon>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.icons.insert(uri.clone(), icon).is_none() {
+            self.order.push_back(uri);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.icons.remove(&oldest);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.icons.clear();
+        self.order.clear();
+    }
+}
+
+/// The parts of a theme's `index.theme` needed for lookups.
+#[derive(Debug, Default)]
+struct ThemeIndex {
+    inherits: Vec<String>,
+    directories: Vec<ThemeDirectory>,
+}
+
+#[derive(Debug)]
+struct ThemeDirectory {
+    path: String,
+    size: u32,
+    kind: String,
+    min_size: Option<u32>,
+    max_size: Option<u32>,
+    threshold: u32,
+}
+
+impl ThemeDirectory {
+    /// How far icons of this directory are from `size`, 0 if they fit.
+    fn distance(&self, size: u32) -> u32 {
+        match self.kind.as_str() {
+            "Scalable" => {
+                let min = self.min_size.unwrap_or(self.size);
+                let max = self.max_size.unwrap_or(self.size);
+                min.saturating_sub(size) + size.saturating_sub(max)
+            }
+            "Fixed" => self.size.abs_diff(size),
+            _ => self.size.abs_diff(size).saturating_sub(self.threshold),
+        }
+    }
+}
+
+impl ThemeIndex {
+    fn parse(input: &str) -> Self {
+        let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+        let mut section = String::new();
+        for line in input.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+                section = name.to_string();
+            } else if let Some((key, value)) = line.split_once('=') {
+                sections.entry(section.clone()).or_default().insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+
+        let list = |value: Option<&String>| -> Vec<String> {
+            value.map(|it| it.split(',').map(str::trim).filter(|it| !it.is_empty()).map(String::from).collect())
+                .unwrap_or_default()
+        };
+        let Some(theme) = sections.get("Icon Theme") else {
+            return Self::default();
+        };
+        let directories = list(theme.get("Directories")).into_iter()
+            .filter_map(|path| {
+                let entries = sections.get(&path)?;
+                let number = |key: &str| entries.get(key).and_then(|it| it.parse().ok());
+                Some(ThemeDirectory {
+                    size: number("Size")?,
+                    kind: entries.get("Type").cloned().unwrap_or("Threshold".to_string()),
+                    min_size: number("MinSize"),
+                    max_size: number("MaxSize"),
+                    threshold: number("Threshold").unwrap_or(2),
+                    path,
+                })
+            })
+            .collect();
+
+        Self { inherits: list(theme.get("Inherits")), directories }
+    }
+}
+
+fn default_search_paths() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let data_home = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+        .or(home.as_ref().map(|it| it.join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS").ok()
+        .filter(|it| !it.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share".to_string());
+
+    let mut paths: Vec<PathBuf> = home.map(|it| it.join(".icons")).into_iter().collect();
+    paths.extend(data_home.map(|it| it.join("icons")));
+    paths.extend(data_dirs.split(':').map(|it| Path::new(it).join("icons")));
+    paths.push(PathBuf::from("/usr/share/pixmaps"));
+    paths
+}
+
+fn read_file(path: &Path) -> Option<Icon> {
+    let data = std::fs::read(path).ok()?;
+    let mime_type = match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "svg" => Some("image/svg+xml"),
+        "xpm" => Some("image/x-xpixmap"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "bmp" => Some("image/bmp"),
+        _ => None,
+    };
+    Some(Icon { data, mime_type: mime_type.map(String::from) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use test_log::test;
+    use crate::playlist_icon::IconResolver;
+
+    fn theme_dir() -> anyhow::Result<PathBuf> {
+        let base = std::env::temp_dir().join(format!("zmpris-icons-{}", std::process::id()));
+        let theme = base.join("test-theme");
+        std::fs::create_dir_all(theme.join("16x16/apps"))?;
+        std::fs::create_dir_all(theme.join("48x48/apps"))?;
+        std::fs::write(theme.join("index.theme"), "\
+            [Icon Theme]\n\
+            Name=Test\n\
+            Directories=16x16/apps,48x48/apps\n\
+            \n\
+            [16x16/apps]\n\
+            Size=16\n\
+            Type=Fixed\n\
+            \n\
+            [48x48/apps]\n\
+            Size=48\n\
+            Type=Fixed\n")?;
+        std::fs::write(theme.join("16x16/apps/music.png"), b"small")?;
+        std::fs::write(theme.join("48x48/apps/music.png"), b"large")?;
+        std::fs::write(base.join("cover art.jpg"), b"cover")?;
+        Ok(base)
+    }
+
+    #[test(tokio::test)]
+    async fn resolve_icons() -> anyhow::Result<()> {
+        let base = theme_dir()?;
+        let resolver = IconResolver::new()
+            .with_theme("test-theme")
+            .with_size(48)
+            .with_search_paths(vec![base.clone()]);
+
+        let icon = resolver.resolve("music").expect("Theme icon not found");
+        assert_eq!(icon.data, b"large");
+        assert_eq!(icon.mime_type.as_deref(), Some("image/png"));
+        let small = IconResolver::new().with_theme("test-theme").with_size(16).with_search_paths(vec![base.clone()]);
+        assert_eq!(small.resolve("music").expect("Theme icon not found").data, b"small");
+
+        let uri = format!("file://{}/cover%20art.jpg", base.display());
+        let icon = resolver.resolve(&uri).expect("File icon not found");
+        assert_eq!(icon.data, b"cover");
+        assert_eq!(icon.mime_type.as_deref(), Some("image/jpeg"));
+        assert!(resolver.resolve(&format!("file://localhost{}/cover%20art.jpg", base.display())).is_some());
+        // Neither remote files nor plain paths are read
+        assert!(resolver.resolve(&format!("file://example.com{}/cover%20art.jpg", base.display())).is_none());
+        assert!(resolver.resolve(&base.join("cover art.jpg").display().to_string()).is_none());
+        assert!(resolver.resolve("../cover art").is_none());
+
+        let icon = resolver.resolve("data:image/png;base64,aGVsbG8gd29ybGQ=").expect("Data URI not decoded");
+        assert_eq!(icon.data, b"hello world");
+        assert_eq!(icon.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(resolver.resolve("data:,a%20b").expect("Data URI not decoded").data, b"a b");
+
+        assert!(resolver.resolve("").is_none());
+        assert!(resolver.resolve("missing-icon").is_none());
+        assert!(resolver.resolve("https://example.com/icon.png").is_none());
+
+        // Served from the cache until cleared
+        std::fs::remove_dir_all(&base)?;
+        assert!(resolver.resolve(&uri).is_some());
+        resolver.clear_cache();
+        assert!(resolver.resolve(&uri).is_none());
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn bounded_cache() -> anyhow::Result<()> {
+        let base = std::env::temp_dir().join(format!("zmpris-icons-bounded-{}", std::process::id()));
+        std::fs::create_dir_all(&base)?;
+        std::fs::write(base.join("a.png"), b"a")?;
+        std::fs::write(base.join("b.png"), b"b")?;
+        let resolver = IconResolver::new().with_search_paths(vec![base.clone()]).with_cache_capacity(1);
+
+        assert!(resolver.resolve("a").is_some());
+        assert!(resolver.resolve("b").is_some());
+        std::fs::remove_dir_all(&base)?;
+        // Only the latest result is kept
+        assert!(resolver.resolve("b").is_some());
+        assert!(resolver.resolve("a").is_none());
+
+        anyhow::Ok(())
+    }
+}
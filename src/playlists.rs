@@ -11,7 +11,7 @@ use zvariant::ObjectPath;
     interface = "org.mpris.MediaPlayer2.Playlists",
     default_path = "/org/mpris/MediaPlayer2"
 )]
-trait Playlists {
+pub trait Playlists {
     /// Starts playing the given playlist.
     ///
     /// Note that this must be implemented.  If the media player does not
@@ -92,7 +92,7 @@ trait Playlists {
     fn playlist_count(&self) -> zbus::Result<u32>;
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sync"))]
 mod tests {
     use super::*;
     use crate::sync::discovery::by_name;
@@ -0,0 +1,50 @@
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+use futures::future::{self, Either};
+
+/// Runs `f` on a thread pool for blocking work, like file system access.
+pub(crate) async fn unblock<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    return tokio::task::spawn_blocking(f).await.expect("Blocking task panicked");
+    #[cfg(not(feature = "tokio"))]
+    blocking::unblock(f).await
+}
+
+/// Runs `future` to completion on the executor that drives the blocking API of zbus.
+#[cfg(feature = "blocking")]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    zbus::block_on(future)
+}
+
+/// Waits for `duration` with the timer of the async runtime.
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(not(feature = "tokio"))]
+    async_io::Timer::after(duration).await;
+}
+
+/// Error of [`timeout`] when `duration` elapsed first.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Elapsed;
+
+impl std::fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Deadline elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Runs `future` for at most `duration`.
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    match future::select(pin!(future), pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use zbus::fdo::{DBusProxy, NameAcquiredStream, NameLostStream, RequestNameFlags, RequestNameReply};
+use zbus::names::{BusName, OwnedWellKnownName, WellKnownName};
+use zbus::Connection;
+use crate::shared::BASE_PATH;
+
+/// How [`PlayerName::acquire`] requests the name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameOptions {
+    /// Let other connections take the name over.
+    pub allow_replacement: bool,
+    /// Take the name over if its owner allows replacement.
+    pub replace_existing: bool,
+    /// Wait in the queue for the name while it's taken.
+    pub queue: bool,
+    /// Use `org.mpris.MediaPlayer2.<identity>.instance<pid>` while the name is taken.
+    pub instance_fallback: bool,
+}
+
+impl Default for NameOptions {
+    fn default() -> Self {
+        Self {
+            allow_replacement: false,
+            replace_existing: false,
+            queue: false,
+            instance_fallback: true,
+        }
+    }
+}
+
+/// A change of the name owned by a [`PlayerName`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameEvent {
+    Acquired(OwnedWellKnownName),
+    Lost(OwnedWellKnownName),
+}
+
+/// The bus name of an MPRIS server.
+///
+/// Servers own `org.mpris.MediaPlayer2.<identity>`, or
+/// `org.mpris.MediaPlayer2.<identity>.instance<pid>` if another instance
+/// already does. The owned name can change later: the name may be taken over
+/// by another connection, or a queued request may succeed, in which case the
+/// instance name is released. Such changes are applied while reading events
+/// with [`next_event`](Self::next_event).
+pub struct PlayerName {
+    conn: Connection,
+    base: OwnedWellKnownName,
+    instance: OwnedWellKnownName,
+    options: NameOptions,
+    current: Option<OwnedWellKnownName>,
+    pending: VecDeque<NameEvent>,
+    acquired: NameAcquiredStream,
+    lost: NameLostStream,
+}
+
+impl PlayerName {
+    /// Requests the name of the player called `identity`.
+    ///
+    /// Fails with [`zbus::Error::NameTaken`] if neither the name nor the
+    /// instance name could be acquired, unless queueing. A queued request
+    /// succeeds without a name, see [`name`](Self::name).
+    pub async fn acquire(conn: &Connection, identity: &str, options: NameOptions) -> zbus::Result<Self> {
+        Self::acquire_name(conn, &format!("{BASE_PATH}{identity}"), options).await
+    }
+
+    /// Same as [`acquire`](Self::acquire), for a full `name`.
+    pub(crate) async fn acquire_name(conn: &Connection, name: &str, options: NameOptions) -> zbus::Result<Self> {
+        let base: OwnedWellKnownName = WellKnownName::try_from(name.to_string())?.into();
+        let instance = WellKnownName::try_from(format!("{base}.instance{}", std::process::id()))?.into();
+
+        let dbus = DBusProxy::new(conn).await?;
+        let mut player_name = Self {
+            conn: conn.clone(),
+            acquired: dbus.receive_name_acquired().await?,
+            lost: dbus.receive_name_lost().await?,
+            base,
+            instance,
+            options,
+            current: None,
+            pending: VecDeque::new(),
+        };
+
+        let flags = [
+            (options.allow_replacement, RequestNameFlags::AllowReplacement),
+            (options.replace_existing, RequestNameFlags::ReplaceExisting),
+            (!options.queue, RequestNameFlags::DoNotQueue),
+        ].into_iter().filter_map(|(enabled, flag)| enabled.then_some(flag)).collect();
+        match taken_as_exists(conn.request_name_with_flags(&player_name.base, flags).await)? {
+            RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => player_name.current = Some(player_name.base.clone()),
+            RequestNameReply::InQueue | RequestNameReply::Exists => {
+                if options.instance_fallback {
+                    player_name.current = player_name.request_instance().await?;
+                }
+                if player_name.current.is_none() && !options.queue {
+                    return Err(zbus::Error::NameTaken);
+                }
+            }
+        }
+
+        Ok(player_name)
+    }
+
+    /// The owned name, [`None`] while waiting in the queue.
+    pub fn name(&self) -> Option<&WellKnownName<'static>> {
+        self.current.as_deref()
+    }
+
+    /// Waits for the next change of the owned name.
+    ///
+    /// When the name is taken over, it falls back to the instance name if
+    /// enabled, which yields [`NameEvent::Lost`] followed by [`NameEvent::Acquired`].
+    /// Returns [`None`] when the connection is closed.
+    pub async fn next_event(&mut self) -> Option<NameEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            match future::select(self.acquired.next(), self.lost.next()).await {
+                Either::Left((acquired, _)) => {
+                    let signal = acquired?;
+                    let Ok(BusName::WellKnown(name)) = signal.args().map(|it| it.name) else {
+                        continue;
+                    };
+                    self.on_acquired(name.into_owned().into()).await;
+                }
+                Either::Right((lost, _)) => {
+                    let signal = lost?;
+                    let Ok(BusName::WellKnown(name)) = signal.args().map(|it| it.name) else {
+                        continue;
+                    };
+                    self.on_lost(name.into_owned().into()).await;
+                }
+            }
+        }
+    }
+
+    /// Releases the owned name and leaves the queue.
+    pub async fn release(self) -> zbus::Result<()> {
+        self.conn.release_name(&self.base).await?;
+        if self.current.as_ref() == Some(&self.instance) {
+            self.conn.release_name(&self.instance).await?;
+        }
+        Ok(())
+    }
+
+    async fn on_acquired(&mut self, name: OwnedWellKnownName) {
+        // Also announced for our own successful requests
+        if name != self.base || self.current.as_ref() == Some(&self.base) {
+            return;
+        }
+        if self.current.as_ref() == Some(&self.instance) {
+            if let Err(e) = self.conn.release_name(&self.instance).await {
+                log::debug!("Failed to release {}: {e}", self.instance);
+            }
+        }
+        self.current = Some(name.clone());
+        self.pending.push_back(NameEvent::Acquired(name));
+    }
+
+    async fn on_lost(&mut self, name: OwnedWellKnownName) {
+        if self.current.as_ref() != Some(&name) {
+            return;
+        }
+        self.current = None;
+        self.pending.push_back(NameEvent::Lost(name.clone()));
+
+        if name == self.base && self.options.instance_fallback {
+            match self.request_instance().await {
+                Ok(Some(instance)) => {
+                    self.current = Some(instance.clone());
+                    self.pending.push_back(NameEvent::Acquired(instance));
+                }
+                Ok(None) => {}
+                Err(e) => log::debug!("Failed to request {}: {e}", self.instance),
+            }
+        }
+    }
+
+    async fn request_instance(&self) -> zbus::Result<Option<OwnedWellKnownName>> {
+        let reply = self.conn.request_name_with_flags(&self.instance, RequestNameFlags::DoNotQueue.into()).await;
+        Ok(match taken_as_exists(reply)? {
+            RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => Some(self.instance.clone()),
+            RequestNameReply::InQueue | RequestNameReply::Exists => None,
+        })
+    }
+}
+
+/// zbus reports [`RequestNameReply::Exists`] as an error.
+fn taken_as_exists(reply: zbus::Result<RequestNameReply>) -> zbus::Result<RequestNameReply> {
+    match reply {
+        Err(zbus::Error::NameTaken) => Ok(RequestNameReply::Exists),
+        reply => reply,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_log::test;
+    use zbus::names::{OwnedWellKnownName, WellKnownName};
+    use zbus::Connection;
+    use crate::server::bus_name::{NameEvent, NameOptions, PlayerName};
+    use crate::shared::BASE_PATH;
+
+    const IDENTITY: &str = "zmpris_test_bus_name";
+
+    fn name(name: &str) -> OwnedWellKnownName {
+        WellKnownName::try_from(name.to_string()).unwrap().into()
+    }
+
+    #[test(tokio::test)]
+    async fn acquire_and_replace() -> anyhow::Result<()> {
+        let base = name(&format!("{BASE_PATH}{IDENTITY}"));
+        let instance = name(&format!("{base}.instance{}", std::process::id()));
+
+        let first_conn = Connection::session().await?;
+        let options = NameOptions { allow_replacement: true, ..Default::default() };
+        let mut first = PlayerName::acquire(&first_conn, IDENTITY, options).await?;
+        assert_eq!(first.name(), Some(&*base));
+
+        // Takes the name over, the first falls back to the instance name
+        let second_conn = Connection::session().await?;
+        let options = NameOptions { replace_existing: true, ..Default::default() };
+        let second = PlayerName::acquire(&second_conn, IDENTITY, options).await?;
+        assert_eq!(second.name(), Some(&*base));
+        assert_eq!(first.next_event().await, Some(NameEvent::Lost(base.clone())));
+        assert_eq!(first.next_event().await, Some(NameEvent::Acquired(instance.clone())));
+        assert_eq!(first.name(), Some(&*instance));
+
+        // Both names are taken, so only queueing works
+        let third_conn = Connection::session().await?;
+        assert!(PlayerName::acquire(&third_conn, IDENTITY, NameOptions::default()).await.is_err());
+        let options = NameOptions { queue: true, ..Default::default() };
+        let mut third = PlayerName::acquire(&third_conn, IDENTITY, options).await?;
+        assert_eq!(third.name(), None);
+
+        second.release().await?;
+        assert_eq!(third.next_event().await, Some(NameEvent::Acquired(base.clone())));
+        assert_eq!(third.name(), Some(&*base));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+use zbus::export::ordered_stream::{join, OrderedStreamExt};
+use zbus::fdo::{self, DBusProxy, NameOwnerChanged, PropertiesChanged};
+use zbus::message::{Flags, Type};
+use zbus::names::{OwnedUniqueName, WellKnownName};
+use zbus::{Connection, MatchRule, Message, MessageStream, Task};
+use zvariant::{OwnedValue, Signature, Structure};
+use crate::server::bus_name::{NameOptions, PlayerName};
+use crate::runtime;
+use crate::shared::{
+    BASE_PATH, DISCOVERY_TIMEOUT, MEDIA_PLAYER_INTERFACE, OBJECT_PATH, PLAYER_INTERFACE, PLAYLISTS_INTERFACE,
+    PROPERTIES_INTERFACE, TRACK_LIST_INTERFACE,
+};
+
+/// Identity of the daemon, which owns `org.mpris.MediaPlayer2.zmpris`.
+pub const DAEMON_IDENTITY: &str = "zmpris";
+
+const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+
+const INTERFACES: [&str; 4] = [
+    MEDIA_PLAYER_INTERFACE,
+    PLAYER_INTERFACE,
+    TRACK_LIST_INTERFACE,
+    PLAYLISTS_INTERFACE,
+];
+
+/// Unique names of the followed players, the most recently used first.
+type Players = Arc<Mutex<Vec<OwnedUniqueName>>>;
+
+/// Re-exports the active media player under a fixed name, like `playerctld`.
+///
+/// Every method call on [`OBJECT_PATH`], including the property calls, is
+/// forwarded to the active player and its reply returned, and the signals of
+/// the active player are emitted again by the daemon. The active player is
+/// the last used one: a player becomes active when it appears on the bus or
+/// starts playing. When the active player changes, the daemon announces the
+/// properties of the new one through `PropertiesChanged`.
+///
+/// The daemon replies to method calls itself, so it needs a connection of its
+/// own on which [`Connection::object_server`] is never used. It stops when
+/// dropped.
+pub struct ProxyDaemon {
+    name: PlayerName,
+    players: Players,
+    _calls: Task<()>,
+    _signals: Task<()>,
+}
+
+impl ProxyDaemon {
+    /// Follows MPRIS players and owns `org.mpris.MediaPlayer2.zmpris`.
+    pub async fn new(conn: &Connection) -> zbus::Result<Self> {
+        Self::with_names(conn, BASE_PATH, &format!("{BASE_PATH}{DAEMON_IDENTITY}")).await
+    }
+
+    /// Follows players owning a name starting with `namespace`, e.g.
+    /// `org.mpris.MediaPlayer2.`, and owns `name`.
+    pub async fn with_names(conn: &Connection, namespace: &str, name: &str) -> zbus::Result<Self> {
+        let signals = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .path(OBJECT_PATH)?
+            .build();
+        let name_owner_changed = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender("org.freedesktop.DBus")?
+            .interface("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .arg0ns(namespace.trim_end_matches('.'))?
+            .build();
+        let calls = MatchRule::builder()
+            .msg_type(Type::MethodCall)
+            .build();
+
+        let signals = join(
+            MessageStream::for_match_rule(signals, conn, None).await?,
+            MessageStream::for_match_rule(name_owner_changed, conn, None).await?,
+        ).into_stream();
+        let calls = MessageStream::for_match_rule(calls, conn, None).await?;
+
+        let owners = players(conn, namespace, name).await?;
+        let players = Players::new(Mutex::new(owners.iter().map(|(owner, _)| owner.clone()).collect()));
+        let name = PlayerName::acquire_name(conn, name, NameOptions { instance_fallback: false, ..Default::default() }).await?;
+        let follower = Follower {
+            conn: conn.clone(),
+            namespace: namespace.to_string(),
+            name: name.name().map(ToString::to_string),
+            players: players.clone(),
+            names: owners.into_iter().collect(),
+        };
+
+        Ok(Self {
+            name,
+            _calls: conn.executor().spawn(forward_calls(conn.clone(), players.clone(), calls), "zmpris daemon calls"),
+            _signals: conn.executor().spawn(follower.run(signals), "zmpris daemon signals"),
+            players,
+        })
+    }
+
+    /// The name owned by the daemon.
+    pub fn name(&self) -> Option<&WellKnownName<'static>> {
+        self.name.name()
+    }
+
+    /// Unique name of the player calls are forwarded to.
+    pub fn active(&self) -> Option<OwnedUniqueName> {
+        active(&self.players)
+    }
+}
+
+fn active(players: &Players) -> Option<OwnedUniqueName> {
+    players.lock()
+        .expect("Daemon players lock poisoned")
+        .first()
+        .cloned()
+}
+
+/// Owners of the players in `namespace` with the number of names they own
+/// there, the playing ones first.
+async fn players(conn: &Connection, namespace: &str, name: &str) -> zbus::Result<Vec<(OwnedUniqueName, usize)>> {
+    let dbus = DBusProxy::new(conn).await?;
+    let mut owners: Vec<(OwnedUniqueName, usize)> = Vec::new();
+    for player in dbus.list_names().await? {
+        if !player.starts_with(namespace) || player.as_str() == name {
+            continue;
+        }
+        let Ok(owner) = dbus.get_name_owner(player.inner().clone()).await else {
+            continue;
+        };
+        match owners.iter_mut().find(|(it, _)| *it == owner) {
+            Some((_, count)) => *count += 1,
+            None => owners.push((owner, 1)),
+        }
+    }
+
+    // Asked concurrently and bounded, so a hung player doesn't hold up the daemon
+    let playing = future::join_all(owners.iter().map(|(owner, _)| async move {
+        runtime::timeout(DISCOVERY_TIMEOUT, is_playing(conn, owner)).await.unwrap_or(false)
+    })).await;
+    let mut players: Vec<_> = owners.into_iter().zip(playing).collect();
+    players.sort_by_key(|(_, playing)| !playing);
+
+    Ok(players.into_iter().map(|(owner, _)| owner).collect())
+}
+
+async fn is_playing(conn: &Connection, owner: &OwnedUniqueName) -> bool {
+    let status = conn.call_method(Some(owner.as_str()), OBJECT_PATH, Some(PROPERTIES_INTERFACE), "Get", &(PLAYER_INTERFACE, "PlaybackStatus")).await;
+    status.is_ok_and(|it| {
+        it.body().deserialize::<OwnedValue>().is_ok_and(|it| <&str>::try_from(&it).is_ok_and(|it| it == "Playing"))
+    })
+}
+
+async fn forward_calls(conn: Connection, players: Players, mut calls: MessageStream) {
+    while let Some(call) = StreamExt::next(&mut calls).await {
+        let Ok(call) = call else {
+            continue;
+        };
+        // Handled concurrently, so a hung player only delays its own calls
+        let (task_conn, players) = (conn.clone(), players.clone());
+        conn.executor().spawn(async move {
+            if let Err(e) = forward_call(&task_conn, &players, &call).await {
+                log::debug!("Failed to forward call: {e}");
+            }
+        }, "zmpris daemon call").detach();
+    }
+}
+
+async fn forward_call(conn: &Connection, players: &Players, call: &Message) -> zbus::Result<()> {
+    let header = call.header();
+    let Some(member) = header.member() else {
+        return Ok(());
+    };
+    let no_reply = call.primary_header().flags().contains(Flags::NoReplyExpected);
+
+    let path = header.path().map(|it| it.as_str()).unwrap_or_default();
+    if path != OBJECT_PATH {
+        let introspect = header.interface().map(|it| it.as_str()) == Some(INTROSPECTABLE_INTERFACE) && member.as_str() == "Introspect";
+        if let Some(child) = child_node(path).filter(|_| introspect) {
+            return conn.reply(&header, &(introspection(child),)).await;
+        }
+        return conn.reply_dbus_error(&header, fdo::Error::UnknownObject(format!("No object at {path}"))).await;
+    }
+    if header.interface().map(|it| it.as_str()) == Some("org.freedesktop.DBus.Peer") && member.as_str() == "Ping" {
+        return conn.reply(&header, &()).await;
+    }
+    let Some(player) = active(players) else {
+        return conn.reply_dbus_error(&header, fdo::Error::Failed("No active player".to_string())).await;
+    };
+
+    let body = call.body();
+    let reply = if body.signature() == &Signature::Unit {
+        conn.call_method(Some(player.as_str()), OBJECT_PATH, header.interface(), member, &()).await
+    } else {
+        let args: Structure<'_> = body.deserialize()?;
+        conn.call_method(Some(player.as_str()), OBJECT_PATH, header.interface(), member, &args).await
+    };
+    if no_reply {
+        return Ok(());
+    }
+
+    match reply {
+        Ok(reply) => {
+            let body = reply.body();
+            if body.signature() == &Signature::Unit {
+                conn.reply(&header, &()).await
+            } else {
+                conn.reply(&header, &body.deserialize::<Structure<'_>>()?).await
+            }
+        }
+        Err(zbus::Error::MethodError(name, description, _)) => {
+            conn.reply_error(&header, name, &description.unwrap_or_default()).await
+        }
+        Err(e) => conn.reply_dbus_error(&header, fdo::Error::Failed(e.to_string())).await,
+    }
+}
+
+/// Name of the node below `path` leading to [`OBJECT_PATH`], [`None`] if
+/// `path` isn't one of its ancestors.
+fn child_node(path: &str) -> Option<&'static str> {
+    let rest = match path {
+        "/" => OBJECT_PATH,
+        _ => OBJECT_PATH.strip_prefix(path)?,
+    };
+    rest.strip_prefix('/')?.split('/').next()
+}
+
+/// Introspection data of a node that only holds the node `child`.
+fn introspection(child: &str) -> String {
+    format!(
+        "<!DOCTYPE node PUBLIC \"-//freedesktop//DTD D-BUS Object Introspection 1.0//EN\"\n \
+         \"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd\">\n\
+         <node>\n  <node name=\"{child}\"/>\n</node>\n"
+    )
+}
+
+/// Tracks the players and re-emits the signals of the active one.
+struct Follower {
+    conn: Connection,
+    namespace: String,
+    name: Option<String>,
+    players: Players,
+    /// Number of names in the namespace owned by each player.
+    names: HashMap<OwnedUniqueName, usize>,
+}
+
+impl Follower {
+    async fn run(mut self, mut signals: impl Stream<Item = zbus::Result<Message>> + Unpin) {
+        while let Some(signal) = signals.next().await {
+            let Ok(signal) = signal else {
+                continue;
+            };
+            if let Err(e) = self.handle(signal).await {
+                log::debug!("Failed to handle signal: {e}");
+            }
+        }
+    }
+
+    async fn handle(&mut self, signal: Message) -> zbus::Result<()> {
+        if let Some(signal) = NameOwnerChanged::from_message(signal.clone()) {
+            let args = signal.args()?;
+            if !args.name().starts_with(&self.namespace) || Some(args.name().as_str()) == self.name.as_deref() {
+                return Ok(());
+            }
+            let previous = active(&self.players);
+            {
+                let mut players = self.players.lock().expect("Daemon players lock poisoned");
+                // Players may own several names, they are only gone with the last one
+                if let Some(old_owner) = args.old_owner().as_ref().map(|it| OwnedUniqueName::from(it.to_owned())) {
+                    let count = self.names.entry(old_owner.clone()).or_default();
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        self.names.remove(&old_owner);
+                        players.retain(|it| *it != old_owner);
+                    }
+                }
+                if let Some(new_owner) = args.new_owner().as_ref().map(|it| OwnedUniqueName::from(it.to_owned())) {
+                    let count = self.names.entry(new_owner.clone()).or_default();
+                    *count += 1;
+                    if *count == 1 {
+                        players.retain(|it| *it != new_owner);
+                        players.insert(0, new_owner);
+                    }
+                }
+            }
+            return self.announce(previous).await;
+        }
+
+        let header = signal.header();
+        let Some(sender) = header.sender().map(|it| OwnedUniqueName::from(it.to_owned())) else {
+            return Ok(());
+        };
+        if !self.players.lock().expect("Daemon players lock poisoned").contains(&sender) {
+            return Ok(());
+        }
+
+        if let Some(changed) = PropertiesChanged::from_message(signal.clone()) {
+            let args = changed.args()?;
+            let playing = args.changed_properties()
+                .get("PlaybackStatus")
+                .is_some_and(|it| it.downcast_ref::<&str>().is_ok_and(|it| it == "Playing"));
+            if args.interface_name() == PLAYER_INTERFACE && playing {
+                let previous = active(&self.players);
+                {
+                    let mut players = self.players.lock().expect("Daemon players lock poisoned");
+                    players.retain(|it| *it != sender);
+                    players.insert(0, sender.clone());
+                }
+                if previous.as_ref() != Some(&sender) {
+                    // Announces every property, which includes this change
+                    return self.announce(previous).await;
+                }
+            }
+        }
+
+        let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+            return Ok(());
+        };
+        if active(&self.players).as_ref() != Some(&sender) {
+            return Ok(());
+        }
+        let body = signal.body();
+        if body.signature() == &Signature::Unit {
+            self.conn.emit_signal(None::<()>, OBJECT_PATH, interface, member, &()).await
+        } else {
+            self.conn.emit_signal(None::<()>, OBJECT_PATH, interface, member, &body.deserialize::<Structure<'_>>()?).await
+        }
+    }
+
+    /// Emits the properties of the active player if it changed since `previous`.
+    async fn announce(&self, previous: Option<OwnedUniqueName>) -> zbus::Result<()> {
+        let Some(player) = active(&self.players) else {
+            return Ok(());
+        };
+        if Some(&player) == previous.as_ref() {
+            return Ok(());
+        }
+
+        for interface in INTERFACES {
+            // Bounded like at startup, the signals of every player wait for this
+            let get_all = runtime::timeout(
+                DISCOVERY_TIMEOUT,
+                self.conn.call_method(Some(player.as_str()), OBJECT_PATH, Some(PROPERTIES_INTERFACE), "GetAll", &(interface,)),
+            ).await;
+            let Ok(reply) = get_all else {
+                log::debug!("{player} didn't answer GetAll in time");
+                return Ok(());
+            };
+            let Ok(reply) = reply else {
+                continue;
+            };
+            let properties: HashMap<String, OwnedValue> = reply.body().deserialize()?;
+            self.conn.emit_signal(
+                None::<()>,
+                OBJECT_PATH,
+                PROPERTIES_INTERFACE,
+                "PropertiesChanged",
+                &(interface, properties, Vec::<&str>::new()),
+            ).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zbus::proxy::CacheProperties;
+    use zbus::Connection;
+    use zvariant::{ObjectPath, OwnedValue, Value};
+    use crate::runtime;
+    use crate::server::daemon::ProxyDaemon;
+    use crate::server::engine::{FakeClock, PlaybackEngine};
+    use crate::server::player::PlayerServer;
+    use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+    use crate::shared::{PlaybackStatus, OBJECT_PATH};
+    use crate::sync::PlayerProxy;
+
+    const NAMESPACE: &str = "org.mpris.MediaPlayer2.zmpris_test.daemon.";
+    const NAME: &str = "org.mpris.MediaPlayer2.zmpris_test.daemon_proxy";
+
+    async fn serve_player(name: &str) -> anyhow::Result<(Connection, PlayerProxy<'static>)> {
+        let conn = Connection::session().await?;
+        let mut engine = PlaybackEngine::new(FakeClock::default());
+        let track = HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(ObjectPath::try_from("/track/1")?).try_to_owned()?),
+            ("mpris:length".to_string(), OwnedValue::from(10_000_000i64)),
+        ]);
+        engine.set_tracks(vec![track], Some(0));
+        let store = StateStore::new(&conn, MediaPlayer2State::default(), PlayerState::default())?;
+        let mut player_server = PlayerServer::new(engine, store);
+        player_server.update_backend(|_| Vec::new()).await?;
+        conn.object_server().at(OBJECT_PATH, player_server).await?;
+        conn.request_name(name).await?;
+
+        let proxy = PlayerProxy::builder(&conn)
+            .destination(conn.unique_name().unwrap().to_owned())?
+            .cache_properties(CacheProperties::No)
+            .build().await?;
+        Ok((conn, proxy))
+    }
+
+    async fn wait_for_active(daemon: &ProxyDaemon, conn: &Connection) -> anyhow::Result<()> {
+        let player = conn.unique_name().unwrap().to_owned();
+        runtime::timeout(Duration::from_secs(5), async {
+            while daemon.active().as_deref() != Some(&player) {
+                runtime::sleep(Duration::from_millis(10)).await;
+            }
+        }).await?;
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn follow_last_used() -> anyhow::Result<()> {
+        let daemon_conn = Connection::session().await?;
+        let daemon = ProxyDaemon::with_names(&daemon_conn, NAMESPACE, NAME).await?;
+        assert_eq!(daemon.name().map(|it| it.as_str()), Some(NAME));
+
+        let conn = Connection::session().await?;
+        let proxy = PlayerProxy::builder(&conn)
+            .destination(NAME)?
+            .cache_properties(CacheProperties::No)
+            .build().await?;
+        let mut seeked = proxy.receive_seeked().await?;
+        assert!(proxy.play().await.is_err());
+
+        let (first_conn, first) = serve_player(&format!("{NAMESPACE}first")).await?;
+        wait_for_active(&daemon, &first_conn).await?;
+        proxy.play().await?;
+        assert_eq!(first.playback_status().await?, PlaybackStatus::Playing);
+        assert_eq!(proxy.playback_status().await?, PlaybackStatus::Playing);
+
+        // Newly appeared players become active
+        let (second_conn, second) = serve_player(&format!("{NAMESPACE}second")).await?;
+        wait_for_active(&daemon, &second_conn).await?;
+        assert_eq!(proxy.playback_status().await?, PlaybackStatus::Stopped);
+        second.set_position(&ObjectPath::try_from("/track/1")?, 2_000_000).await?;
+        assert_eq!(seeked.next().await.expect("No Seeked").args()?.position, 2_000_000);
+
+        // Players become active again when they start playing
+        first.pause().await?;
+        first.play().await?;
+        wait_for_active(&daemon, &first_conn).await?;
+        proxy.pause().await?;
+        assert_eq!(first.playback_status().await?, PlaybackStatus::Paused);
+        assert_eq!(second.playback_status().await?, PlaybackStatus::Stopped);
+
+        // Falls back to the remaining player when the active one exits
+        first_conn.release_name(format!("{NAMESPACE}first")).await?;
+        wait_for_active(&daemon, &second_conn).await?;
+        proxy.play().await?;
+        assert_eq!(second.playback_status().await?, PlaybackStatus::Playing);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn follow_players_with_several_names() -> anyhow::Result<()> {
+        let namespace = "org.mpris.MediaPlayer2.zmpris_test.daemon_names.";
+        let daemon_conn = Connection::session().await?;
+        let daemon = ProxyDaemon::with_names(&daemon_conn, namespace, &format!("{namespace}proxy")).await?;
+
+        let (first_conn, _first) = serve_player(&format!("{namespace}first")).await?;
+        first_conn.request_name(format!("{namespace}first.instance2")).await?;
+        wait_for_active(&daemon, &first_conn).await?;
+
+        // Releasing one of its names doesn't drop the player
+        first_conn.release_name(format!("{namespace}first.instance2")).await?;
+        let (second_conn, _second) = serve_player(&format!("{namespace}second")).await?;
+        wait_for_active(&daemon, &second_conn).await?;
+        second_conn.release_name(format!("{namespace}second")).await?;
+        wait_for_active(&daemon, &first_conn).await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn skip_hung_players() -> anyhow::Result<()> {
+        let namespace = "org.mpris.MediaPlayer2.zmpris_test.daemon_hung.";
+        // Never answers, its object server isn't started
+        let hung = Connection::session().await?;
+        hung.request_name(format!("{namespace}hung")).await?;
+
+        let daemon_conn = Connection::session().await?;
+        let daemon = runtime::timeout(
+            Duration::from_secs(10),
+            ProxyDaemon::with_names(&daemon_conn, namespace, &format!("{namespace}proxy")),
+        ).await??;
+        assert_eq!(daemon.active().as_ref(), hung.unique_name());
+
+        // Nor do players that hang after the daemon started
+        let late = Connection::session().await?;
+        late.request_name(format!("{namespace}late")).await?;
+        let (player_conn, player) = serve_player(&format!("{namespace}player")).await?;
+        wait_for_active(&daemon, &player_conn).await?;
+        let proxy = PlayerProxy::builder(&Connection::session().await?)
+            .destination(format!("{namespace}proxy"))?
+            .cache_properties(CacheProperties::No)
+            .build().await?;
+        proxy.play().await?;
+        assert_eq!(player.playback_status().await?, PlaybackStatus::Playing);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn introspect_parent_nodes() -> anyhow::Result<()> {
+        let namespace = "org.mpris.MediaPlayer2.zmpris_test.daemon_introspect.";
+        let name = format!("{namespace}proxy");
+        let daemon_conn = Connection::session().await?;
+        let _daemon = ProxyDaemon::with_names(&daemon_conn, namespace, &name).await?;
+
+        let conn = Connection::session().await?;
+        for (path, child) in [("/", "org"), ("/org", "mpris"), ("/org/mpris", "MediaPlayer2")] {
+            let reply = conn.call_method(Some(name.as_str()), path, Some("org.freedesktop.DBus.Introspectable"), "Introspect", &()).await?;
+            let xml: String = reply.body().deserialize()?;
+            assert!(xml.contains(&format!("<node name=\"{child}\"/>")), "{path}: {xml}");
+        }
+        assert!(conn.call_method(Some(name.as_str()), "/org/other", Some("org.freedesktop.DBus.Introspectable"), "Introspect", &()).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zvariant::OwnedValue;
+use crate::server::player::PlaybackBackend;
+use crate::server::state::{PlayerState, StateStore};
+use crate::shared::{LoopStatus, Metadata, PlaybackRate, PlaybackStatus, TimeInUs};
+
+/// Source of the current time for a [`PlaybackEngine`].
+pub trait Clock: Send + Sync {
+    /// Time elapsed since an arbitrary, fixed point.
+    fn now(&self) -> Duration;
+}
+
+/// The monotonic system clock.
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+///
+/// Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct FakeClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl FakeClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("Fake clock lock poisoned") += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().expect("Fake clock lock poisoned")
+    }
+}
+
+/// A change of a [`PlaybackEngine`] that clients must be told about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// The position jumped, to be announced with `Seeked`.
+    Seeked(TimeInUs),
+    /// The current track changed to the track at the given index, if any.
+    TrackChanged(Option<usize>),
+    StatusChanged(PlaybackStatus),
+}
+
+/// Simulated playback of a list of tracks.
+///
+/// The position advances with the clock while playing, scaled by the rate.
+/// When the current track ends, which [`tick`](Self::tick) checks, the
+/// engine repeats it, moves on or stops according to the loop status,
+/// following the shuffled order if shuffle is on. Tracks without
+/// `mpris:length` never end.
+///
+/// Nothing is sent over D-Bus: operations return the [`EngineEvent`]s they
+/// caused and [`publish`](Self::publish) announces them through a [`StateStore`].
+pub struct PlaybackEngine<C: Clock = SystemClock> {
+    clock: C,
+    tracks: Vec<HashMap<String, OwnedValue>>,
+    /// Order in which tracks are played, a permutation of their indices.
+    order: Vec<usize>,
+    /// Index into `order` of the current track.
+    current: Option<usize>,
+    status: PlaybackStatus,
+    rate: PlaybackRate,
+    loop_status: LoopStatus,
+    shuffle: bool,
+    /// Position at `anchor_time`, the position advances from there while playing.
+    anchor_position: TimeInUs,
+    anchor_time: Duration,
+    seed: u64,
+}
+
+impl Default for PlaybackEngine {
+    fn default() -> Self {
+        Self::new(SystemClock::default())
+    }
+}
+
+impl<C: Clock> PlaybackEngine<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            anchor_time: clock.now(),
+            clock,
+            tracks: Vec::new(),
+            order: Vec::new(),
+            current: None,
+            status: PlaybackStatus::Stopped,
+            rate: 1.0,
+            loop_status: LoopStatus::None,
+            shuffle: false,
+            anchor_position: 0,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// Seed of the shuffled order, to make it reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // Xorshift gets stuck at 0
+        self.seed = seed.max(1);
+        self
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        self.status
+    }
+
+    pub fn rate(&self) -> PlaybackRate {
+        self.rate
+    }
+
+    pub fn loop_status(&self) -> LoopStatus {
+        self.loop_status
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    pub fn tracks(&self) -> &[HashMap<String, OwnedValue>] {
+        &self.tracks
+    }
+
+    /// Index of the current track in [`tracks`](Self::tracks).
+    pub fn current(&self) -> Option<usize> {
+        self.current.map(|it| self.order[it])
+    }
+
+    /// Position in the current track, never past its end.
+    pub fn position(&self) -> TimeInUs {
+        let position = match self.status {
+            PlaybackStatus::Playing => {
+                let elapsed = self.clock.now().saturating_sub(self.anchor_time).as_micros() as f64;
+                self.anchor_position + (elapsed * self.rate) as TimeInUs
+            }
+            _ => self.anchor_position,
+        };
+        match self.length() {
+            Some(length) => position.clamp(0, length),
+            None => position.max(0),
+        }
+    }
+
+    /// Time until the current track ends, to know when to [`tick`](Self::tick).
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.status != PlaybackStatus::Playing || self.rate <= 0.0 {
+            return None;
+        }
+        let remaining = (self.length()? - self.position()).max(0) as f64 / self.rate;
+        Some(Duration::from_micros(remaining as u64))
+    }
+
+    /// Whether there is a track after the current one, wrapping when looping the playlist.
+    pub fn has_next(&self) -> bool {
+        self.current.is_some_and(|it| it + 1 < self.order.len() || self.loop_status == LoopStatus::Playlist)
+    }
+
+    /// Whether there is a track before the current one, wrapping when looping the playlist.
+    pub fn has_previous(&self) -> bool {
+        self.current.is_some_and(|it| it > 0 || self.loop_status == LoopStatus::Playlist)
+    }
+
+    /// Replaces the tracks, making the track at `current` current and stopping playback.
+    pub fn set_tracks(&mut self, tracks: Vec<HashMap<String, OwnedValue>>, current: Option<usize>) -> Vec<EngineEvent> {
+        let previous = self.current();
+        self.tracks = tracks;
+        let current = current.filter(|it| *it < self.tracks.len());
+        self.reorder(current);
+
+        let mut events = self.set_status(PlaybackStatus::Stopped);
+        self.anchor(0);
+        // Even the same index is a different track now
+        if previous.is_some() || self.current().is_some() {
+            events.push(EngineEvent::TrackChanged(self.current()));
+        }
+        events
+    }
+
+    /// Appends a track, returning its index.
+    pub fn add_track(&mut self, metadata: HashMap<String, OwnedValue>) -> usize {
+        self.tracks.push(metadata);
+        self.order.push(self.tracks.len() - 1);
+        self.tracks.len() - 1
+    }
+
+    /// Makes the track at `index` current, keeping the playback status.
+    pub fn go_to(&mut self, index: usize) -> Vec<EngineEvent> {
+        match self.order.iter().position(|it| *it == index) {
+            Some(position) => self.change_track(Some(position)),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn play(&mut self) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        if self.current.is_none() {
+            if self.order.is_empty() {
+                return events;
+            }
+            events.extend(self.change_track(Some(0)));
+        }
+        let position = self.position();
+        events.extend(self.set_status(PlaybackStatus::Playing));
+        self.anchor(position);
+        events
+    }
+
+    pub fn pause(&mut self) -> Vec<EngineEvent> {
+        if self.status != PlaybackStatus::Playing {
+            return Vec::new();
+        }
+        let position = self.position();
+        self.anchor(position);
+        self.set_status(PlaybackStatus::Paused)
+    }
+
+    pub fn play_pause(&mut self) -> Vec<EngineEvent> {
+        match self.status {
+            PlaybackStatus::Playing => self.pause(),
+            _ => self.play(),
+        }
+    }
+
+    pub fn stop(&mut self) -> Vec<EngineEvent> {
+        self.anchor(0);
+        self.set_status(PlaybackStatus::Stopped)
+    }
+
+    /// Moves to the next track, stopping after the last unless looping the playlist.
+    pub fn next_track(&mut self) -> Vec<EngineEvent> {
+        let Some(current) = self.current else {
+            return Vec::new();
+        };
+        if current + 1 < self.order.len() {
+            self.change_track(Some(current + 1))
+        } else if self.loop_status == LoopStatus::Playlist {
+            self.change_track(Some(0))
+        } else {
+            self.stop()
+        }
+    }
+
+    /// Moves to the previous track, restarting the first one unless looping the playlist.
+    pub fn previous_track(&mut self) -> Vec<EngineEvent> {
+        let Some(current) = self.current else {
+            return Vec::new();
+        };
+        if current > 0 {
+            self.change_track(Some(current - 1))
+        } else if self.loop_status == LoopStatus::Playlist {
+            self.change_track(Some(self.order.len() - 1))
+        } else {
+            self.set_position(0)
+        }
+    }
+
+    /// Jumps to `position` in the current track, clamped to the track.
+    pub fn set_position(&mut self, position: TimeInUs) -> Vec<EngineEvent> {
+        if self.current.is_none() {
+            return Vec::new();
+        }
+        let position = match self.length() {
+            Some(length) => position.clamp(0, length),
+            None => position.max(0),
+        };
+        self.anchor(position);
+        vec![EngineEvent::Seeked(position)]
+    }
+
+    /// Jumps by `offset` from the current position, see [`set_position`](Self::set_position).
+    pub fn seek(&mut self, offset: TimeInUs) -> Vec<EngineEvent> {
+        self.set_position(self.position().saturating_add(offset))
+    }
+
+    /// Sets the rate, rates that aren't positive are ignored.
+    ///
+    /// The specification asks to pause at a rate of 0, which is up to the caller.
+    pub fn set_rate(&mut self, rate: PlaybackRate) {
+        if rate.is_nan() || rate <= 0.0 {
+            return;
+        }
+        let position = self.position();
+        self.rate = rate;
+        self.anchor(position);
+    }
+
+    pub fn set_loop_status(&mut self, loop_status: LoopStatus) {
+        self.loop_status = loop_status;
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if self.shuffle != shuffle {
+            self.shuffle = shuffle;
+            self.reorder(self.current());
+        }
+    }
+
+    /// Handles the end of the current track if it has been reached.
+    pub fn tick(&mut self) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        while self.status == PlaybackStatus::Playing {
+            let Some(length) = self.length().filter(|it| *it > 0) else {
+                break;
+            };
+            let elapsed = self.clock.now().saturating_sub(self.anchor_time).as_micros() as f64;
+            let position = self.anchor_position + (elapsed * self.rate) as TimeInUs;
+            if position < length {
+                break;
+            }
+
+            // Whatever played past the end counts for the following track
+            let overflow = position - length;
+            let overflow_time = Duration::from_micros((overflow as f64 / self.rate) as u64);
+            match self.loop_status {
+                LoopStatus::Track => {
+                    self.anchor(0);
+                    events.push(EngineEvent::Seeked(0));
+                }
+                _ => events.extend(self.next_track()),
+            }
+            if self.status == PlaybackStatus::Playing {
+                self.anchor_time = self.clock.now().saturating_sub(overflow_time);
+            }
+        }
+        events
+    }
+
+    /// Copies the state into `player`, including the metadata of the current track.
+    pub fn sync(&self, player: &mut PlayerState) -> zbus::Result<()> {
+        let has_track = self.current.is_some();
+        player.playback_status = self.status;
+        player.rate = self.rate;
+        player.loop_status = self.loop_status;
+        player.shuffle = self.shuffle;
+        player.position = self.position();
+        player.can_go_next = self.has_next();
+        player.can_go_previous = self.has_previous();
+        player.can_play = has_track || !self.tracks.is_empty();
+        player.can_pause = has_track;
+        player.can_seek = has_track;
+        player.metadata = match self.current() {
+            Some(index) => self.tracks[index].iter()
+                .map(|(key, value)| Ok((key.clone(), value.try_clone()?)))
+                .collect::<zbus::Result<_>>()?,
+            None => HashMap::new(),
+        };
+        Ok(())
+    }
+
+    /// Copies the state into `store`, announces property changes and emits
+    /// `Seeked` for the given events.
+    pub async fn publish(&self, store: &mut StateStore, events: &[EngineEvent]) -> zbus::Result<()> {
+        self.sync(store.player_mut())?;
+        store.flush().await?;
+        for event in events {
+            if let EngineEvent::Seeked(position) = event {
+                store.emit_seeked(*position).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn length(&self) -> Option<TimeInUs> {
+        Metadata::from(&self.tracks[self.current()?]).length
+    }
+
+    fn anchor(&mut self, position: TimeInUs) {
+        self.anchor_position = position;
+        self.anchor_time = self.clock.now();
+    }
+
+    fn set_status(&mut self, status: PlaybackStatus) -> Vec<EngineEvent> {
+        if self.status == status {
+            return Vec::new();
+        }
+        self.status = status;
+        vec![EngineEvent::StatusChanged(status)]
+    }
+
+    fn change_track(&mut self, position: Option<usize>) -> Vec<EngineEvent> {
+        self.current = position;
+        self.anchor(0);
+        vec![EngineEvent::TrackChanged(self.current())]
+    }
+
+    /// Rebuilds the play order, keeping `current` as the current track.
+    fn reorder(&mut self, current: Option<usize>) {
+        self.order = (0..self.tracks.len()).collect();
+        if self.shuffle {
+            // Fisher-Yates with xorshift, the current track goes first
+            for index in (1..self.order.len()).rev() {
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 7;
+                self.seed ^= self.seed << 17;
+                self.order.swap(index, (self.seed % (index as u64 + 1)) as usize);
+            }
+            if let Some(current) = current {
+                let position = self.order.iter().position(|it| *it == current).unwrap();
+                self.order.swap(0, position);
+            }
+        }
+        self.current = current.and_then(|current| self.order.iter().position(|it| *it == current));
+    }
+}
+
+impl<C: Clock + 'static> PlaybackBackend for PlaybackEngine<C> {
+    fn position(&self) -> TimeInUs {
+        PlaybackEngine::position(self)
+    }
+
+    fn play(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::play(self)
+    }
+
+    fn pause(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::pause(self)
+    }
+
+    fn play_pause(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::play_pause(self)
+    }
+
+    fn stop(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::stop(self)
+    }
+
+    fn next_track(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::next_track(self)
+    }
+
+    fn previous_track(&mut self) -> Vec<EngineEvent> {
+        PlaybackEngine::previous_track(self)
+    }
+
+    fn set_position(&mut self, position: TimeInUs) -> Vec<EngineEvent> {
+        PlaybackEngine::set_position(self, position)
+    }
+
+    fn set_rate(&mut self, rate: PlaybackRate) {
+        PlaybackEngine::set_rate(self, rate)
+    }
+
+    fn set_loop_status(&mut self, loop_status: LoopStatus) {
+        PlaybackEngine::set_loop_status(self, loop_status)
+    }
+
+    fn set_shuffle(&mut self, shuffle: bool) {
+        PlaybackEngine::set_shuffle(self, shuffle)
+    }
+
+    fn open(&mut self, metadata: HashMap<String, OwnedValue>) -> Vec<EngineEvent> {
+        let index = self.add_track(metadata);
+        let mut events = self.go_to(index);
+        if self.status == PlaybackStatus::Stopped {
+            events.extend(PlaybackEngine::play(self));
+        }
+        events
+    }
+
+    fn sync(&self, player: &mut PlayerState) -> zbus::Result<()> {
+        PlaybackEngine::sync(self, player)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use test_log::test;
+    use zvariant::{OwnedValue, Value};
+    use crate::server::engine::{EngineEvent, FakeClock, PlaybackEngine};
+    use crate::shared::{LoopStatus, PlaybackStatus};
+
+    fn tracks(count: usize) -> Vec<HashMap<String, OwnedValue>> {
+        (0..count)
+            .map(|_| HashMap::from([("mpris:length".to_string(), Value::from(10_000_000i64).try_to_owned().unwrap())]))
+            .collect()
+    }
+
+    #[test]
+    fn advance_with_clock() -> anyhow::Result<()> {
+        let clock = FakeClock::default();
+        let mut engine = PlaybackEngine::new(clock.clone());
+        engine.set_tracks(tracks(2), None);
+
+        assert_eq!(engine.play(), [EngineEvent::TrackChanged(Some(0)), EngineEvent::StatusChanged(PlaybackStatus::Playing)]);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(engine.position(), 1_000_000);
+
+        engine.set_rate(2.0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(engine.position(), 3_000_000);
+        // Not a valid rate
+        engine.set_rate(-1.0);
+        engine.set_rate(f64::NAN);
+        assert_eq!(engine.rate(), 2.0);
+        assert_eq!(engine.remaining(), Some(Duration::from_millis(3_500)));
+
+        engine.pause();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(engine.position(), 3_000_000);
+
+        assert_eq!(engine.seek(-5_000_000), [EngineEvent::Seeked(0)]);
+        assert_eq!(engine.set_position(20_000_000), [EngineEvent::Seeked(10_000_000)]);
+
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn end_of_track() -> anyhow::Result<()> {
+        let clock = FakeClock::default();
+        let mut engine = PlaybackEngine::new(clock.clone());
+        engine.set_tracks(tracks(2), Some(0));
+        engine.play();
+
+        // Repeats the track
+        engine.set_loop_status(LoopStatus::Track);
+        clock.advance(Duration::from_secs(12));
+        assert_eq!(engine.tick(), [EngineEvent::Seeked(0)]);
+        assert_eq!(engine.position(), 2_000_000);
+
+        // Wraps around the playlist
+        engine.set_loop_status(LoopStatus::Playlist);
+        clock.advance(Duration::from_secs(18));
+        assert_eq!(engine.tick(), [EngineEvent::TrackChanged(Some(1)), EngineEvent::TrackChanged(Some(0))]);
+        assert_eq!(engine.position(), 0);
+
+        // Stops after the last track
+        engine.set_loop_status(LoopStatus::None);
+        clock.advance(Duration::from_secs(25));
+        assert_eq!(engine.tick(), [EngineEvent::TrackChanged(Some(1)), EngineEvent::StatusChanged(PlaybackStatus::Stopped)]);
+        assert_eq!(engine.position(), 0);
+        assert!(!engine.has_next());
+
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn shuffle() -> anyhow::Result<()> {
+        let clock = FakeClock::default();
+        let mut engine = PlaybackEngine::new(clock.clone()).with_seed(42);
+        engine.set_tracks(tracks(8), Some(3));
+        engine.set_shuffle(true);
+        assert_eq!(engine.current(), Some(3));
+
+        engine.play();
+        let mut played = vec![engine.current().unwrap()];
+        while engine.has_next() {
+            clock.advance(Duration::from_secs(10));
+            engine.tick();
+            played.push(engine.current().unwrap());
+        }
+        assert_ne!(played, [3, 4, 5, 6, 7, 0, 1, 2]);
+        played.sort();
+        assert_eq!(played, (0..8).collect::<Vec<_>>());
+
+        anyhow::Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use zbus::object_server::InterfaceRef;
+use zbus::{fdo, interface, Connection};
+use crate::server::engine::PlaybackEngine;
+use crate::server::player::{PlaybackBackend, PlayerServer};
+use crate::shared::OBJECT_PATH;
+
+/// The exported root `org.mpris.MediaPlayer2` interface of a [`PlayerServer`].
+///
+/// Properties are read from the `MediaPlayer2State` of the player's
+/// [`StateStore`](crate::server::state::StateStore), which also announces
+/// their changes. `Raise`, `Quit` and setting `Fullscreen` are passed to the
+/// [`PlaybackBackend`] if `CanRaise`, `CanQuit` and `CanSetFullscreen` allow it.
+pub struct MediaPlayer2Server<B: PlaybackBackend = PlaybackEngine> {
+    player: InterfaceRef<PlayerServer<B>>,
+}
+
+impl<B: PlaybackBackend> MediaPlayer2Server<B> {
+    pub fn new(player: InterfaceRef<PlayerServer<B>>) -> Self {
+        Self { player }
+    }
+
+    /// Exports `player` and its root interface at [`OBJECT_PATH`] on `conn`.
+    pub async fn serve(conn: &Connection, player: PlayerServer<B>) -> zbus::Result<InterfaceRef<PlayerServer<B>>> {
+        let object_server = conn.object_server();
+        object_server.at(OBJECT_PATH, player).await?;
+        let player = object_server.interface::<_, PlayerServer<B>>(OBJECT_PATH).await?;
+        object_server.at(OBJECT_PATH, Self::new(player.clone())).await?;
+        Ok(player)
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl<B: PlaybackBackend> MediaPlayer2Server<B> {
+    async fn raise(&self) {
+        self.player.get_mut().await.raise();
+    }
+
+    async fn quit(&self) {
+        self.player.get_mut().await.quit();
+    }
+
+    #[zbus(property)]
+    async fn can_quit(&self) -> bool {
+        self.player.get().await.store().media_player().can_quit
+    }
+
+    #[zbus(property)]
+    async fn fullscreen(&self) -> bool {
+        self.player.get().await.store().media_player().fullscreen
+    }
+
+    #[zbus(property)]
+    async fn set_fullscreen(&mut self, value: bool) -> fdo::Result<()> {
+        self.player.get_mut().await.set_fullscreen(value).await
+    }
+
+    #[zbus(property)]
+    async fn can_set_fullscreen(&self) -> bool {
+        self.player.get().await.store().media_player().can_set_fullscreen
+    }
+
+    #[zbus(property)]
+    async fn can_raise(&self) -> bool {
+        self.player.get().await.store().media_player().can_raise
+    }
+
+    #[zbus(property)]
+    async fn has_track_list(&self) -> bool {
+        self.player.get().await.store().media_player().has_track_list
+    }
+
+    #[zbus(property)]
+    async fn identity(&self) -> String {
+        self.player.get().await.store().media_player().identity.clone()
+    }
+
+    #[zbus(property)]
+    async fn desktop_entry(&self) -> String {
+        self.player.get().await.store().media_player().desktop_entry.clone()
+    }
+
+    #[zbus(property)]
+    async fn supported_uri_schemes(&self) -> Vec<String> {
+        self.player.get().await.store().media_player().supported_uri_schemes.clone()
+    }
+
+    #[zbus(property)]
+    async fn supported_mime_types(&self) -> Vec<String> {
+        self.player.get().await.store().media_player().supported_mime_types.clone()
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zbus::fdo::PropertiesProxy;
+    use zbus::Connection;
+    use zvariant::Value;
+    use crate::server::engine::PlaybackEngine;
+    use crate::server::media_player::MediaPlayer2Server;
+    use crate::server::player::PlayerServer;
+    use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+    use crate::shared::OBJECT_PATH;
+    use crate::sync::MediaPlayer2Proxy;
+
+    #[test(tokio::test)]
+    async fn root_properties() -> anyhow::Result<()> {
+        let server = Connection::session().await?;
+        let media_player = MediaPlayer2State {
+            identity: "zmpris".to_string(),
+            can_set_fullscreen: true,
+            supported_uri_schemes: vec!["file".to_string()],
+            ..Default::default()
+        };
+        let store = StateStore::new(&server, media_player, PlayerState::default())?;
+        let player = MediaPlayer2Server::serve(&server, PlayerServer::new(PlaybackEngine::default(), store)).await?;
+
+        let conn = Connection::session().await?;
+        let proxy = MediaPlayer2Proxy::builder(&conn)
+            .destination(server.unique_name().unwrap().to_owned())?
+            .build().await?;
+        let properties = PropertiesProxy::builder(&conn)
+            .destination(server.unique_name().unwrap().to_owned())?
+            .path(OBJECT_PATH)?
+            .build().await?;
+        let mut changes = properties.receive_properties_changed().await?;
+        assert_eq!(proxy.identity().await?, "zmpris");
+        assert_eq!(proxy.supported_uri_schemes().await?, ["file"]);
+        assert!(!proxy.can_quit().await?);
+        // Ignored without `CanQuit`
+        proxy.quit().await?;
+
+        // Announced once, by the object server
+        proxy.set_fullscreen(true).await?;
+        let signal = changes.next().await.expect("No PropertiesChanged");
+        assert_eq!(signal.args()?.changed_properties().keys().collect::<Vec<_>>(), [&"Fullscreen"]);
+        assert!(player.get().await.store().media_player().fullscreen);
+
+        player.get_mut().await.store_mut().update_media_player(|it| it.identity = "Renamed".to_string()).await?;
+        let signal = changes.next().await.expect("No PropertiesChanged");
+        assert_eq!(signal.args()?.changed_properties()["Identity"], Value::from("Renamed"));
+
+        Ok(())
+    }
+}
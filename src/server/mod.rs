@@ -0,0 +1,16 @@
+//! Building blocks for exporting MPRIS interfaces from a media player.
+//!
+//! The interfaces are served with [`zbus::object_server::ObjectServer`] at
+//! [`OBJECT_PATH`](crate::shared::OBJECT_PATH). State is changed through
+//! [`zbus::object_server::InterfaceRef`]s, passing their
+//! [`signal_emitter`](zbus::object_server::InterfaceRef::signal_emitter) so
+//! the matching signals are emitted.
+
+pub mod bus_name;
+pub mod daemon;
+pub mod engine;
+pub mod media_player;
+pub mod player;
+pub mod playlists;
+pub mod state;
+pub mod track_list;
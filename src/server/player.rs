@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use zbus::{fdo, interface};
+use zvariant::{ObjectPath, OwnedValue, Value};
+use crate::playlist_file::scheme;
+use crate::server::engine::{Clock, EngineEvent, PlaybackEngine};
+use crate::server::state::{PlayerState, StateStore};
+use crate::shared::{LoopStatus, Metadata, PlaybackRate, TimeInUs, Volume};
+
+/// Base path of the ids of tracks opened with `OpenUri`.
+pub const OPENED_ID_BASE: &str = "/zmpris/opened";
+
+/// What a [`PlayerServer`] plays with, e.g. a [`PlaybackEngine`] or the
+/// pipeline of a real player.
+///
+/// The server checks requests against the specification before calling the
+/// backend, so implementations only carry them out. Operations return the
+/// [`EngineEvent`]s they caused, which the server announces after copying
+/// the state with [`sync`](Self::sync).
+pub trait PlaybackBackend: Send + Sync + 'static {
+    /// Current position in the current track.
+    fn position(&self) -> TimeInUs;
+    fn play(&mut self) -> Vec<EngineEvent>;
+    fn pause(&mut self) -> Vec<EngineEvent>;
+    fn play_pause(&mut self) -> Vec<EngineEvent>;
+    fn stop(&mut self) -> Vec<EngineEvent>;
+    fn next_track(&mut self) -> Vec<EngineEvent>;
+    fn previous_track(&mut self) -> Vec<EngineEvent>;
+    /// Jumps to `position`, which is within the current track.
+    fn set_position(&mut self, position: TimeInUs) -> Vec<EngineEvent>;
+    /// Sets the rate, which is positive and between the minimum and maximum rate.
+    fn set_rate(&mut self, rate: PlaybackRate);
+    fn set_loop_status(&mut self, loop_status: LoopStatus);
+    fn set_shuffle(&mut self, shuffle: bool);
+    /// Makes a new track with `metadata` current, starting playback if stopped.
+    fn open(&mut self, metadata: HashMap<String, OwnedValue>) -> Vec<EngineEvent>;
+    /// Copies the state into `player`, including the metadata of the current track.
+    fn sync(&self, player: &mut PlayerState) -> zbus::Result<()>;
+
+    /// Brings the user interface to the front, only called if `CanRaise`.
+    fn raise(&mut self) {}
+
+    /// Quits the player, only called if `CanQuit`.
+    fn quit(&mut self) {}
+
+    /// Enters or leaves fullscreen, only called if `CanSetFullscreen`.
+    fn set_fullscreen(&mut self, _fullscreen: bool) {}
+}
+
+/// An exported `org.mpris.MediaPlayer2.Player` playing with a [`PlaybackBackend`].
+///
+/// Method calls and property changes from clients follow the edge cases of
+/// the specification:
+/// - Calls are ignored when the matching `Can*` property is false, `PlayPause`
+///   and `Stop` fail instead as the specification asks.
+/// - `SetPosition` ignores stale track ids and positions outside the track.
+/// - `Seek` stops at the start of the track and acts like `Next` past its end.
+/// - A negative volume is set as 0, other rates are clamped between
+///   `MinimumRate` and `MaximumRate`. A rate of 0 acts like `Pause` and
+///   leaves `Rate` unchanged.
+/// - `LoopStatus`, `Shuffle` and `Rate` can't be set if `CanControl` is false.
+///
+/// Changes are announced through the [`StateStore`], which also provides
+/// the `SupportedUriSchemes` checked by `OpenUri`. The root interface is
+/// exported next to it with
+/// [`MediaPlayer2Server`](crate::server::media_player::MediaPlayer2Server).
+/// With a [`PlaybackEngine`], call [`tick`](Self::tick) when
+/// [`PlaybackEngine::remaining`] has elapsed to move past the end of tracks.
+pub struct PlayerServer<B: PlaybackBackend = PlaybackEngine> {
+    backend: B,
+    store: StateStore,
+    opened: u64,
+}
+
+impl<B: PlaybackBackend> PlayerServer<B> {
+    pub fn new(backend: B, store: StateStore) -> Self {
+        Self { backend, store, opened: 0 }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn store(&self) -> &StateStore {
+        &self.store
+    }
+
+    /// The store, e.g. to change the `org.mpris.MediaPlayer2` properties.
+    ///
+    /// `Player` properties are overwritten by the backend on the next change.
+    pub fn store_mut(&mut self) -> &mut StateStore {
+        &mut self.store
+    }
+
+    /// Changes the backend, e.g. to replace the tracks, and announces the result.
+    pub async fn update_backend(
+        &mut self,
+        update: impl FnOnce(&mut B) -> Vec<EngineEvent>,
+    ) -> zbus::Result<()> {
+        let events = update(&mut self.backend);
+        self.backend.sync(self.store.player_mut())?;
+        self.store.flush().await?;
+        self.emit_seeked(&events).await
+    }
+
+    async fn set_property(&mut self, property: &str, update: impl FnOnce(&mut B, &mut StateStore) -> Vec<EngineEvent>) -> fdo::Result<()> {
+        let events = update(&mut self.backend, &mut self.store);
+        self.backend.sync(self.store.player_mut())?;
+        self.store.flush_except(Some(property)).await?;
+        self.emit_seeked(&events).await?;
+        Ok(())
+    }
+
+    async fn emit_seeked(&self, events: &[EngineEvent]) -> zbus::Result<()> {
+        for event in events {
+            if let EngineEvent::Seeked(position) = event {
+                self.store.emit_seeked(*position).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `Raise` of the root interface, ignored unless `CanRaise`.
+    pub(crate) fn raise(&mut self) {
+        if self.store.media_player().can_raise {
+            self.backend.raise();
+        }
+    }
+
+    /// `Quit` of the root interface, ignored unless `CanQuit`.
+    pub(crate) fn quit(&mut self) {
+        if self.store.media_player().can_quit {
+            self.backend.quit();
+        }
+    }
+
+    /// Sets `Fullscreen` of the root interface, which fails unless `CanSetFullscreen`.
+    pub(crate) async fn set_fullscreen(&mut self, fullscreen: bool) -> fdo::Result<()> {
+        if !self.store.media_player().can_set_fullscreen {
+            return Err(fdo::Error::NotSupported("The player can't set fullscreen".to_string()));
+        }
+        self.set_property("Fullscreen", |backend, store| {
+            backend.set_fullscreen(fullscreen);
+            store.media_player_mut().fullscreen = fullscreen;
+            Vec::new()
+        }).await
+    }
+
+    fn ensure_can_control(&self) -> fdo::Result<()> {
+        match self.store.player().can_control {
+            true => Ok(()),
+            false => Err(fdo::Error::AccessDenied("The player can't be controlled".to_string())),
+        }
+    }
+
+    fn length(&self) -> Option<TimeInUs> {
+        Metadata::from(&self.store.player().metadata).length
+    }
+}
+
+impl<C: Clock + 'static> PlayerServer<PlaybackEngine<C>> {
+    /// Handles the end of the current track, see [`PlaybackEngine::tick`].
+    pub async fn tick(&mut self) -> zbus::Result<()> {
+        self.update_backend(PlaybackEngine::tick).await
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl<B: PlaybackBackend> PlayerServer<B> {
+    async fn next(&mut self) -> fdo::Result<()> {
+        if self.store.player().can_go_next {
+            self.update_backend(B::next_track).await?;
+        }
+        Ok(())
+    }
+
+    async fn previous(&mut self) -> fdo::Result<()> {
+        if self.store.player().can_go_previous {
+            self.update_backend(B::previous_track).await?;
+        }
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> fdo::Result<()> {
+        if self.store.player().can_pause {
+            self.update_backend(B::pause).await?;
+        }
+        Ok(())
+    }
+
+    async fn play_pause(&mut self) -> fdo::Result<()> {
+        if !self.store.player().can_pause {
+            return Err(fdo::Error::NotSupported("The player can't pause".to_string()));
+        }
+        self.update_backend(B::play_pause).await?;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> fdo::Result<()> {
+        self.ensure_can_control()?;
+        self.update_backend(B::stop).await?;
+        Ok(())
+    }
+
+    async fn play(&mut self) -> fdo::Result<()> {
+        if self.store.player().can_play {
+            self.update_backend(B::play).await?;
+        }
+        Ok(())
+    }
+
+    async fn seek(&mut self, offset: TimeInUs) -> fdo::Result<()> {
+        if !self.store.player().can_seek {
+            return Ok(());
+        }
+        let target = self.backend.position().saturating_add(offset);
+        if self.length().is_some_and(|it| target > it) {
+            return self.next().await;
+        }
+        self.update_backend(|backend| backend.set_position(target.max(0))).await?;
+        Ok(())
+    }
+
+    async fn set_position(&mut self, track_id: ObjectPath<'_>, position: TimeInUs) -> fdo::Result<()> {
+        let player = self.store.player();
+        // The parameter shadows `track_id`
+        let current = crate::shared::track_id(&player.metadata);
+        if !player.can_seek || current.as_deref() != Some(&track_id) {
+            return Ok(());
+        }
+        if position < 0 || self.length().is_some_and(|it| position > it) {
+            return Ok(());
+        }
+        self.update_backend(|backend| backend.set_position(position)).await?;
+        Ok(())
+    }
+
+    async fn open_uri(&mut self, uri: &str) -> fdo::Result<()> {
+        let supported = scheme(uri).is_some_and(|scheme| {
+            self.store.media_player().supported_uri_schemes.iter().any(|it| it.eq_ignore_ascii_case(scheme))
+        });
+        if !supported {
+            return Err(fdo::Error::NotSupported(format!("Unsupported URI {uri}")));
+        }
+
+        self.opened += 1;
+        let id = ObjectPath::try_from(format!("{OPENED_ID_BASE}/{}", self.opened)).map_err(zbus::Error::Variant)?;
+        let metadata: HashMap<String, OwnedValue> = HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(id).try_to_owned().map_err(zbus::Error::Variant)?),
+            ("xesam:url".to_string(), Value::from(uri).try_to_owned().map_err(zbus::Error::Variant)?),
+        ]);
+        self.update_backend(|backend| backend.open(metadata)).await?;
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &zbus::object_server::SignalEmitter<'_>, position: TimeInUs) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.store.player().playback_status.into()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        self.store.player().loop_status.into()
+    }
+
+    #[zbus(property)]
+    async fn set_loop_status(&mut self, value: String) -> fdo::Result<()> {
+        self.ensure_can_control()?;
+        let loop_status = match value.as_str() {
+            "None" | "Track" | "Playlist" => LoopStatus::from(value.as_str()),
+            _ => return Err(fdo::Error::InvalidArgs(format!("Unknown loop status {value}"))),
+        };
+        self.set_property("LoopStatus", |backend, _| {
+            backend.set_loop_status(loop_status);
+            Vec::new()
+        }).await
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> PlaybackRate {
+        self.store.player().rate
+    }
+
+    #[zbus(property)]
+    async fn set_rate(&mut self, value: PlaybackRate) -> fdo::Result<()> {
+        self.ensure_can_control()?;
+        if value == 0.0 {
+            return self.pause().await;
+        }
+        let (minimum, maximum) = (self.store.player().minimum_rate, self.store.player().maximum_rate);
+        let rate = if value.is_nan() { 1.0 } else { value.clamp(minimum, maximum) };
+        self.set_property("Rate", |backend, _| {
+            backend.set_rate(rate);
+            Vec::new()
+        }).await
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.store.player().shuffle
+    }
+
+    #[zbus(property)]
+    async fn set_shuffle(&mut self, value: bool) -> fdo::Result<()> {
+        self.ensure_can_control()?;
+        self.set_property("Shuffle", |backend, _| {
+            backend.set_shuffle(value);
+            Vec::new()
+        }).await
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> fdo::Result<HashMap<String, OwnedValue>> {
+        self.store.player().metadata.iter()
+            .map(|(key, value)| Ok((key.clone(), value.try_clone().map_err(zbus::Error::Variant)?)))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> Volume {
+        self.store.player().volume
+    }
+
+    #[zbus(property)]
+    async fn set_volume(&mut self, value: Volume) -> fdo::Result<()> {
+        self.ensure_can_control()?;
+        let volume = if value.is_nan() { 0.0 } else { value.max(0.0) };
+        self.set_property("Volume", |_, store| {
+            store.player_mut().volume = volume;
+            Vec::new()
+        }).await
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> TimeInUs {
+        self.backend.position()
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> PlaybackRate {
+        self.store.player().minimum_rate
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> PlaybackRate {
+        self.store.player().maximum_rate
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.store.player().can_go_next
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.store.player().can_go_previous
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.store.player().can_play
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.store.player().can_pause
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.store.player().can_seek
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        self.store.player().can_control
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zbus::Connection;
+    use zvariant::{ObjectPath, OwnedValue, Value};
+    use crate::server::engine::{FakeClock, PlaybackEngine};
+    use crate::server::player::PlayerServer;
+    use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+    use crate::shared::{Metadata, PlaybackStatus, OBJECT_PATH};
+    use crate::sync::PlayerProxy;
+
+    fn track(id: &str) -> HashMap<String, OwnedValue> {
+        HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(ObjectPath::try_from(id).unwrap()).try_to_owned().unwrap()),
+            ("mpris:length".to_string(), Value::from(10_000_000i64).try_to_owned().unwrap()),
+        ])
+    }
+
+    #[test(tokio::test)]
+    async fn validate_requests() -> anyhow::Result<()> {
+        let server = Connection::session().await?;
+        let clock = FakeClock::default();
+        let mut engine = PlaybackEngine::new(clock.clone());
+        engine.set_tracks(vec![track("/track/1"), track("/track/2")], Some(0));
+        let media_player = MediaPlayer2State { supported_uri_schemes: vec!["file".to_string()], ..Default::default() };
+        let player = PlayerState { minimum_rate: 0.5, maximum_rate: 2.0, ..Default::default() };
+        let mut player_server = PlayerServer::new(engine, StateStore::new(&server, media_player, player)?);
+        player_server.update_backend(|_| Vec::new()).await?;
+        server.object_server().at(OBJECT_PATH, player_server).await?;
+
+        let conn = Connection::session().await?;
+        let proxy = PlayerProxy::builder(&conn)
+            .destination(server.unique_name().unwrap().to_owned())?
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build().await?;
+        let mut seeked = proxy.receive_seeked().await?;
+        let current = || async { Metadata::from(&proxy.metadata().await.unwrap()).track_id.map(|it| it.to_string()) };
+
+        proxy.play().await?;
+        assert_eq!(proxy.playback_status().await?, PlaybackStatus::Playing);
+
+        proxy.set_volume(-0.5).await?;
+        assert_eq!(proxy.volume().await?, 0.0);
+        proxy.set_rate(5.0).await?;
+        assert_eq!(proxy.rate().await?, 2.0);
+        proxy.set_rate(0.0).await?;
+        assert_eq!(proxy.playback_status().await?, PlaybackStatus::Paused);
+        assert_eq!(proxy.rate().await?, 2.0);
+
+        // Stale track ids and out of range positions are ignored
+        let stale = ObjectPath::try_from("/track/2")?;
+        proxy.set_position(&stale, 1_000_000).await?;
+        let first = ObjectPath::try_from("/track/1")?;
+        proxy.set_position(&first, 20_000_000).await?;
+        proxy.set_position(&first, -1).await?;
+        assert_eq!(proxy.position().await?, 0);
+        proxy.set_position(&first, 4_000_000).await?;
+        assert_eq!(seeked.next().await.expect("No Seeked").args()?.position, 4_000_000);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(proxy.position().await?, 4_000_000);
+
+        // Seeking before the start stops there, past the end acts like `Next`
+        proxy.seek(-10_000_000).await?;
+        assert_eq!(seeked.next().await.expect("No Seeked").args()?.position, 0);
+        proxy.seek(20_000_000).await?;
+        assert_eq!(current().await.as_deref(), Some("/track/2"));
+
+        // No next track, so `Next` has no effect
+        assert!(!proxy.can_go_next().await?);
+        proxy.next().await?;
+        assert_eq!(current().await.as_deref(), Some("/track/2"));
+
+        assert!(proxy.open_uri("http://example.com/stream").await.is_err());
+        proxy.open_uri("file:///music/a.mp3").await?;
+        let metadata = Metadata::from(&proxy.metadata().await?);
+        assert_eq!(metadata.url.as_deref(), Some("file:///music/a.mp3"));
+        assert_eq!(proxy.playback_status().await?, PlaybackStatus::Paused);
+
+        anyhow::Ok(())
+    }
+}
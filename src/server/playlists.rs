@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zbus::fdo;
+use zbus::interface;
+use zbus::object_server::{ObjectServer, SignalEmitter};
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use crate::playlist_file::{self, PlaylistEntry, PlaylistFormat};
+use crate::runtime;
+use crate::server::track_list::TrackListServer;
+use crate::shared::{Playlist, PlaylistOrdering, OBJECT_PATH};
+use crate::uri::file_uri;
+
+/// Default base path of generated playlist ids.
+pub const DEFAULT_PLAYLIST_ID_BASE: &str = "/zmpris/playlist";
+/// Default name of the play history file, inside the playlist directory.
+pub const DEFAULT_HISTORY_FILE: &str = ".zmpris-history";
+/// Name of the file listing playlist file names for the `User` ordering.
+pub const USER_ORDER_FILE: &str = ".zmpris-order";
+
+const ICON_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "svg"];
+
+const ORDERINGS: [PlaylistOrdering; 5] = [
+    PlaylistOrdering::Alphabetical,
+    PlaylistOrdering::Created,
+    PlaylistOrdering::Modified,
+    PlaylistOrdering::Played,
+    PlaylistOrdering::User,
+];
+
+struct PlaylistFile {
+    playlist: Playlist,
+    path: PathBuf,
+    file_name: String,
+    created: SystemTime,
+    modified: SystemTime,
+}
+
+/// An exported `org.mpris.MediaPlayer2.Playlists` serving the M3U and XSPF
+/// files of a directory.
+///
+/// Playlists are named after the title stored in the file, or the file
+/// name without extension, and use an image with the same file stem as
+/// icon. The directory is read on creation and by [`rescan`](Self::rescan).
+///
+/// All orderings are offered by default: `Created` and `Modified` use the
+/// file times, `Played` a history file updated on activation and `User` the
+/// order of file names listed in [`USER_ORDER_FILE`]. Activating a playlist
+/// replaces the tracks of a [`TrackListServer`] served at the same path, if any.
+pub struct PlaylistsServer {
+    directory: PathBuf,
+    history_file: PathBuf,
+    files: Vec<PlaylistFile>,
+    /// Last activation per file name, in seconds since the epoch.
+    history: HashMap<String, u64>,
+    active: Option<Playlist>,
+    orderings: Vec<PlaylistOrdering>,
+}
+
+impl PlaylistsServer {
+    /// Creates a server for the playlists in `directory`, keeping the play
+    /// history in [`DEFAULT_HISTORY_FILE`] inside it.
+    pub fn new(directory: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let directory = directory.into();
+        let history_file = directory.join(DEFAULT_HISTORY_FILE);
+        let mut server = Self {
+            files: scan(&directory)?,
+            history: read_history(&history_file),
+            directory,
+            history_file,
+            active: None,
+            orderings: ORDERINGS.to_vec(),
+        };
+        server.files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(server)
+    }
+
+    /// Keeps the play history in `history_file` instead.
+    pub fn with_history_file(mut self, history_file: impl Into<PathBuf>) -> Self {
+        self.history_file = history_file.into();
+        self.history = read_history(&self.history_file);
+        self
+    }
+
+    /// Only offers `orderings`, `GetPlaylists` rejects the others.
+    ///
+    /// The specification requires at least one ordering, so an empty list is ignored.
+    pub fn with_orderings(mut self, orderings: impl IntoIterator<Item = PlaylistOrdering>) -> Self {
+        let orderings: Vec<PlaylistOrdering> = orderings.into_iter().collect();
+        if !orderings.is_empty() {
+            self.orderings = orderings;
+        }
+        self
+    }
+
+    /// The served playlists, in file name order.
+    pub fn playlist_list(&self) -> Vec<Playlist> {
+        self.files.iter().map(|it| it.playlist.clone()).collect()
+    }
+
+    /// Path of the file of the playlist with the given id.
+    pub fn path(&self, id: &ObjectPath<'_>) -> Option<&Path> {
+        self.file(id).map(|it| it.path.as_path())
+    }
+
+    /// The currently active playlist.
+    pub fn active(&self) -> Option<&Playlist> {
+        self.active.as_ref()
+    }
+
+    /// Reads the directory again, emitting `PlaylistChanged` for playlists
+    /// whose name or icon changed and property changes as needed.
+    ///
+    /// `PlaylistCount` is announced whenever playlists were added or removed,
+    /// even if their number stayed the same.
+    pub async fn rescan(&mut self, emitter: &SignalEmitter<'_>) -> zbus::Result<()> {
+        let directory = self.directory.clone();
+        let mut files = runtime::unblock(move || scan(&directory)).await
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+        files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        let previous = std::mem::replace(&mut self.files, files);
+
+        for file in &self.files {
+            let changed = previous.iter()
+                .find(|it| it.playlist.id() == file.playlist.id())
+                .is_some_and(|it| it.playlist != file.playlist);
+            if changed {
+                Self::playlist_changed(emitter, &file.playlist).await?;
+            }
+        }
+        let id = |it: &PlaylistFile| it.playlist.id().clone();
+        if !previous.iter().map(id).eq(self.files.iter().map(id)) {
+            self.playlist_count_changed(emitter).await?;
+        }
+
+        if let Some(active) = &self.active {
+            let current = self.file(active.id()).map(|it| it.playlist.clone());
+            if current.as_ref() != Some(active) {
+                self.active = current;
+                self.active_playlist_changed(emitter).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn file(&self, id: &ObjectPath<'_>) -> Option<&PlaylistFile> {
+        self.files.iter().find(|it| it.playlist.id().as_ref() == *id)
+    }
+
+    /// `user_order` is the content of the [`USER_ORDER_FILE`], only used by the `User` ordering.
+    fn ordered(&self, order: PlaylistOrdering, reverse_order: bool, user_order: &str) -> Vec<&PlaylistFile> {
+        let mut files: Vec<&PlaylistFile> = self.files.iter().collect();
+        let by_name = |a: &&PlaylistFile, b: &&PlaylistFile| {
+            a.playlist.name().to_lowercase().cmp(&b.playlist.name().to_lowercase())
+                .then_with(|| a.file_name.cmp(&b.file_name))
+        };
+        match order {
+            PlaylistOrdering::Alphabetical => files.sort_by(by_name),
+            PlaylistOrdering::Created => files.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| by_name(a, b))),
+            PlaylistOrdering::Modified => files.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| by_name(a, b))),
+            PlaylistOrdering::Played => {
+                // Never played counts as played the longest time ago
+                let played = |it: &PlaylistFile| self.history.get(&it.file_name).copied().unwrap_or(0);
+                files.sort_by(|a, b| played(a).cmp(&played(b)).then_with(|| by_name(a, b)))
+            }
+            PlaylistOrdering::User => {
+                let position = |it: &PlaylistFile| user_order.lines().map(str::trim).position(|line| line == it.file_name);
+                files.sort_by(|a, b| match (position(a), position(b)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => by_name(a, b),
+                })
+            }
+        }
+        if reverse_order {
+            files.reverse();
+        }
+        files
+    }
+
+    async fn record_played(&mut self, file_name: String) -> std::io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |it| it.as_secs());
+        // Activations within the same second still have to sort by recency
+        let latest = self.history.values().max().copied().unwrap_or(0);
+        let played = now.max(latest + 1);
+        self.history.insert(file_name.clone(), played);
+
+        let history_file = self.history_file.clone();
+        runtime::unblock(move || {
+            let mut history = std::fs::OpenOptions::new().create(true).append(true).open(history_file)?;
+            writeln!(history, "{played}\t{file_name}")
+        }).await
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl PlaylistsServer {
+    /// Makes the playlist active and loads its tracks into the tracklist.
+    async fn activate_playlist(
+        &mut self,
+        playlist_id: ObjectPath<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        let file = self.file(&playlist_id)
+            .ok_or(fdo::Error::InvalidArgs(format!("Unknown playlist {playlist_id}")))?;
+        let playlist = file.playlist.clone();
+        let file_name = file.file_name.clone();
+        let path = file.path.clone();
+        let entries = runtime::unblock(move || playlist_file::parse_file(&path)).await
+            .map_err(|e| fdo::Error::IOError(e.to_string()))?;
+
+        if let Ok(track_list) = server.interface::<_, TrackListServer>(OBJECT_PATH).await {
+            let tracks = entries.iter().map(entry_metadata).collect::<zbus::Result<_>>()?;
+            let current = (!entries.is_empty()).then_some(0);
+            track_list.get_mut().await.replace(track_list.signal_emitter(), tracks, current).await?;
+        }
+
+        if let Err(e) = self.record_played(file_name).await {
+            log::warn!("Failed to update play history {}: {e}", self.history_file.display());
+        }
+        self.active = Some(playlist);
+        self.active_playlist_changed(&emitter).await?;
+        Ok(())
+    }
+
+    async fn get_playlists(&self, index: u32, max_count: u32, order: &str, reverse_order: bool) -> fdo::Result<Vec<Playlist>> {
+        let order = PlaylistOrdering::from_name(order).ok()
+            .filter(|it| self.orderings.contains(it))
+            .ok_or(fdo::Error::InvalidArgs(format!("Unsupported ordering {order}")))?;
+        let user_order = match order {
+            PlaylistOrdering::User => {
+                let path = self.directory.join(USER_ORDER_FILE);
+                runtime::unblock(move || std::fs::read_to_string(path).unwrap_or_default()).await
+            }
+            _ => String::new(),
+        };
+        Ok(self.ordered(order, reverse_order, &user_order).into_iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .map(|it| it.playlist.clone())
+            .collect())
+    }
+
+    #[zbus(signal)]
+    async fn playlist_changed(emitter: &SignalEmitter<'_>, playlist: &Playlist) -> zbus::Result<()>;
+
+    /// Encoded as `(b(oss))`, with an empty playlist if none is active.
+    #[zbus(property)]
+    fn active_playlist(&self) -> (bool, Playlist) {
+        match &self.active {
+            Some(playlist) => (true, playlist.clone()),
+            None => (false, Playlist::new(ObjectPath::from_static_str_unchecked("/").into(), String::new(), String::new())),
+        }
+    }
+
+    #[zbus(property)]
+    fn orderings(&self) -> Vec<String> {
+        self.orderings.iter().copied().map(String::from).collect()
+    }
+
+    #[zbus(property)]
+    fn playlist_count(&self) -> u32 {
+        self.files.len() as u32
+    }
+}
+
+fn scan(directory: &Path) -> std::io::Result<Vec<PlaylistFile>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let format = match PlaylistFormat::from_path(&path) {
+            Some(format @ (PlaylistFormat::M3u | PlaylistFormat::Xspf)) => format,
+            _ => continue,
+        };
+        match read_playlist(&path, format) {
+            Ok(file) => files.push(file),
+            Err(e) => log::debug!("Skipping playlist {}: {e}", path.display()),
+        }
+    }
+    Ok(files)
+}
+
+fn read_playlist(path: &Path, format: PlaylistFormat) -> std::io::Result<PlaylistFile> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+    let file_name = path.file_name().and_then(|it| it.to_str()).ok_or(invalid("File name is not UTF-8"))?;
+    let stem = path.file_stem().and_then(|it| it.to_str()).unwrap_or(file_name);
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?;
+
+    let name = playlist_file::parse_title(format, &playlist_file::read_text(path)?).unwrap_or(stem.to_string());
+    let icon = ICON_EXTENSIONS.iter()
+        .map(|it| path.with_extension(it))
+        .find(|it| it.is_file())
+        .and_then(|it| std::path::absolute(it).ok())
+        .map(|it| file_uri(&it))
+        .unwrap_or_default();
+    let id = ObjectPath::try_from(format!("{DEFAULT_PLAYLIST_ID_BASE}/{}", encode_path_element(file_name)))
+        .map_err(|e| invalid(&e.to_string()))?;
+
+    Ok(PlaylistFile {
+        playlist: Playlist::new(OwnedObjectPath::from(id), name, icon),
+        path: path.to_path_buf(),
+        file_name: file_name.to_string(),
+        created: metadata.created().unwrap_or(modified),
+        modified,
+    })
+}
+
+/// Encodes `value` using only characters valid in an object path element.
+fn encode_path_element(value: &str) -> String {
+    value.bytes()
+        .map(|it| match it {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (it as char).to_string(),
+            _ => format!("_{it:02X}"),
+        })
+        .collect()
+}
+
+fn read_history(path: &Path) -> HashMap<String, u64> {
+    let history = std::fs::read_to_string(path).unwrap_or_default();
+    let mut played = HashMap::new();
+    for line in history.lines() {
+        let Some((time, file_name)) = line.split_once('\t') else {
+            continue;
+        };
+        if let Ok(time) = time.parse::<u64>() {
+            let entry = played.entry(file_name.to_string()).or_insert(time);
+            *entry = time.max(*entry);
+        }
+    }
+    played
+}
+
+fn entry_metadata(entry: &PlaylistEntry) -> zbus::Result<HashMap<String, OwnedValue>> {
+    let mut metadata = HashMap::from([("xesam:url".to_string(), Value::from(entry.location.as_str()).try_to_owned()?)]);
+    if let Some(title) = &entry.title {
+        metadata.insert("xesam:title".to_string(), Value::from(title.as_str()).try_to_owned()?);
+    }
+    if !entry.artist.is_empty() {
+        metadata.insert("xesam:artist".to_string(), Value::from(entry.artist.clone()).try_to_owned()?);
+    }
+    if let Some(album) = &entry.album {
+        metadata.insert("xesam:album".to_string(), Value::from(album.as_str()).try_to_owned()?);
+    }
+    if let Some(length) = entry.duration {
+        metadata.insert("mpris:length".to_string(), Value::from(length).try_to_owned()?);
+    }
+    Ok(metadata)
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use futures::stream::{StreamExt, TryStreamExt};
+    use test_log::test;
+    use zbus::fdo::PropertiesProxy;
+    use zvariant::Value;
+    use crate::server::playlists::{PlaylistsServer, USER_ORDER_FILE};
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::shared::{Playlist, PlaylistOrdering, OBJECT_PATH};
+    use crate::sync::playlist_pages::playlists;
+    use crate::sync::{PlaylistsProxy, TrackListProxy};
+    use crate::test_util::{self, playlist_dir, proxy, serve};
+
+    fn directory() -> anyhow::Result<PathBuf> {
+        let directory = playlist_dir("playlists", &[
+            ("road.m3u", "#EXTM3U\n#PLAYLIST:Road Trip\n/music/a.mp3\n/music/b.mp3\n"),
+            ("chill.xspf", "<playlist><title>Chill</title><trackList>\
+                <track><location>file:///music/c.mp3</location><title>C</title></track>\
+                </trackList></playlist>"),
+            ("zzz.m3u", "/music/d.mp3\n"),
+            ("zzz.png", "icon"),
+            ("notes.txt", "not a playlist"),
+            (USER_ORDER_FILE, "zzz.m3u\nroad.m3u\n"),
+        ])?;
+
+        // Modified: zzz, chill, road
+        let now = SystemTime::now();
+        for (index, name) in ["zzz.m3u", "chill.xspf", "road.m3u"].iter().enumerate() {
+            let file = std::fs::File::options().write(true).open(directory.join(name))?;
+            file.set_modified(now - Duration::from_secs(100 - index as u64 * 10))?;
+        }
+        Ok(directory)
+    }
+
+    async fn names(proxy: &PlaylistsProxy<'_>, order: PlaylistOrdering) -> anyhow::Result<Vec<String>> {
+        let all: Vec<Playlist> = playlists(proxy, order, false).try_collect().await?;
+        Ok(all.iter().map(|it| it.name().to_string()).collect())
+    }
+
+    #[test(tokio::test)]
+    async fn serve_playlists() -> anyhow::Result<()> {
+        let directory = directory()?;
+        let (server, iface) = serve(PlaylistsServer::new(&directory)?).await?;
+        server.object_server().at(OBJECT_PATH, TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let proxy: PlaylistsProxy = proxy(&server).await?;
+
+        assert_eq!(proxy.playlist_count().await?, 3);
+        assert_eq!(proxy.orderings().await?.0.len(), 5);
+        assert_eq!(names(&proxy, PlaylistOrdering::Alphabetical).await?, ["Chill", "Road Trip", "zzz"]);
+        assert_eq!(names(&proxy, PlaylistOrdering::Modified).await?, ["zzz", "Chill", "Road Trip"]);
+        assert_eq!(names(&proxy, PlaylistOrdering::User).await?, ["zzz", "Road Trip", "Chill"]);
+
+        let all: Vec<Playlist> = playlists(&proxy, PlaylistOrdering::Alphabetical, false).try_collect().await?;
+        assert!(all[2].uri().starts_with("file:///") && all[2].uri().ends_with("/zzz.png"));
+        assert_eq!(proxy.active_playlist().await?.0, None);
+
+        proxy.activate_playlist(all[1].id()).await?;
+        proxy.activate_playlist(all[0].id()).await?;
+        assert_eq!(proxy.active_playlist().await?.0, Some(all[0].clone()));
+        assert_eq!(names(&proxy, PlaylistOrdering::Played).await?, ["zzz", "Road Trip", "Chill"]);
+
+        let track_list: TrackListProxy = test_util::proxy(&server).await?;
+        assert_eq!(track_list.tracks().await?.len(), 1);
+
+        let mut changed = proxy.receive_playlist_changed().await?;
+        std::fs::write(directory.join("road.m3u"), "#EXTM3U\n#PLAYLIST:Long Road Trip\n/music/a.mp3\n")?;
+        iface.get_mut().await.rescan(iface.signal_emitter()).await?;
+        let signal = changed.next().await.expect("No PlaylistChanged");
+        assert_eq!(signal.args()?.playlist.name(), "Long Road Trip");
+        assert_eq!(signal.args()?.playlist.id(), all[1].id());
+
+        // Same count, different playlists
+        let properties = PropertiesProxy::builder(proxy.inner().connection())
+            .destination(server.unique_name().unwrap().to_owned())?
+            .path(OBJECT_PATH)?
+            .build().await?;
+        let mut changes = properties.receive_properties_changed().await?;
+        std::fs::rename(directory.join("zzz.m3u"), directory.join("yyy.m3u"))?;
+        iface.get_mut().await.rescan(iface.signal_emitter()).await?;
+        let signal = changes.next().await.expect("No PropertiesChanged");
+        assert_eq!(signal.args()?.changed_properties()["PlaylistCount"], Value::from(3u32));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
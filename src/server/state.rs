@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use zbus::Connection;
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use crate::shared::{
+    LoopStatus, PlaybackRate, PlaybackStatus, TimeInUs, Volume, MEDIA_PLAYER_INTERFACE, OBJECT_PATH, PLAYER_INTERFACE,
+    PROPERTIES_INTERFACE,
+};
+
+/// Properties of the `org.mpris.MediaPlayer2` interface.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaPlayer2State {
+    pub can_quit: bool,
+    pub fullscreen: bool,
+    pub can_set_fullscreen: bool,
+    pub can_raise: bool,
+    pub has_track_list: bool,
+    pub identity: String,
+    pub desktop_entry: String,
+    pub supported_uri_schemes: Vec<String>,
+    pub supported_mime_types: Vec<String>,
+}
+
+impl MediaPlayer2State {
+    fn emitted_properties(&self) -> zbus::Result<HashMap<&'static str, OwnedValue>> {
+        Ok(HashMap::from([
+            ("CanQuit", owned(self.can_quit)?),
+            ("Fullscreen", owned(self.fullscreen)?),
+            ("CanSetFullscreen", owned(self.can_set_fullscreen)?),
+            ("CanRaise", owned(self.can_raise)?),
+            ("HasTrackList", owned(self.has_track_list)?),
+            ("Identity", owned(self.identity.as_str())?),
+            ("DesktopEntry", owned(self.desktop_entry.as_str())?),
+            ("SupportedUriSchemes", owned(self.supported_uri_schemes.clone())?),
+            ("SupportedMimeTypes", owned(self.supported_mime_types.clone())?),
+        ]))
+    }
+}
+
+/// Properties of the `org.mpris.MediaPlayer2.Player` interface.
+#[derive(Debug, PartialEq)]
+pub struct PlayerState {
+    pub playback_status: PlaybackStatus,
+    pub loop_status: LoopStatus,
+    pub rate: PlaybackRate,
+    pub shuffle: bool,
+    pub metadata: HashMap<String, OwnedValue>,
+    pub volume: Volume,
+    /// Never announced by `PropertiesChanged`, emit `Seeked` on discontinuities instead.
+    pub position: TimeInUs,
+    pub minimum_rate: PlaybackRate,
+    pub maximum_rate: PlaybackRate,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+    pub can_play: bool,
+    pub can_pause: bool,
+    pub can_seek: bool,
+    /// Never announced by `PropertiesChanged`, as it's not expected to change.
+    pub can_control: bool,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        Self {
+            playback_status: PlaybackStatus::Stopped,
+            loop_status: LoopStatus::None,
+            rate: 1.0,
+            shuffle: false,
+            metadata: HashMap::new(),
+            volume: 1.0,
+            position: 0,
+            minimum_rate: 1.0,
+            maximum_rate: 1.0,
+            can_go_next: false,
+            can_go_previous: false,
+            can_play: false,
+            can_pause: false,
+            can_seek: false,
+            can_control: true,
+        }
+    }
+}
+
+impl PlayerState {
+    /// Properties announced by `PropertiesChanged`, i.e. all but `Position`
+    /// and `CanControl`, which the specification annotates with
+    /// `EmitsChangedSignal` `false` and `const`.
+    fn emitted_properties(&self) -> zbus::Result<HashMap<&'static str, OwnedValue>> {
+        let metadata = self.metadata.iter()
+            .map(|(key, value)| Ok((key.as_str(), Value::from(value.try_clone()?))))
+            .collect::<zbus::Result<HashMap<&str, Value<'_>>>>()?;
+        Ok(HashMap::from([
+            ("PlaybackStatus", owned(String::from(self.playback_status))?),
+            ("LoopStatus", owned(String::from(self.loop_status))?),
+            ("Rate", owned(self.rate)?),
+            ("Shuffle", owned(self.shuffle)?),
+            ("Metadata", owned(metadata)?),
+            ("Volume", owned(self.volume)?),
+            ("MinimumRate", owned(self.minimum_rate)?),
+            ("MaximumRate", owned(self.maximum_rate)?),
+            ("CanGoNext", owned(self.can_go_next)?),
+            ("CanGoPrevious", owned(self.can_go_previous)?),
+            ("CanPlay", owned(self.can_play)?),
+            ("CanPause", owned(self.can_pause)?),
+            ("CanSeek", owned(self.can_seek)?),
+        ]))
+    }
+}
+
+/// Holds the state of an MPRIS server and announces its changes.
+///
+/// Changes are staged through [`media_player_mut`](Self::media_player_mut)
+/// and [`player_mut`](Self::player_mut) and announced by
+/// [`flush`](Self::flush), which compares every property with the value
+/// last announced and emits one `PropertiesChanged` per interface with all
+/// that differ. [`update_media_player`](Self::update_media_player) and
+/// [`update_player`](Self::update_player) do both at once.
+///
+/// The properties are those of
+/// [`MediaPlayer2Server`](crate::server::media_player::MediaPlayer2Server)
+/// and [`PlayerServer`](crate::server::player::PlayerServer). `Position`
+/// and `CanControl` are never announced, as the specification requires.
+pub struct StateStore {
+    conn: Connection,
+    path: OwnedObjectPath,
+    media_player: MediaPlayer2State,
+    player: PlayerState,
+    emitted_media_player: HashMap<&'static str, OwnedValue>,
+    emitted_player: HashMap<&'static str, OwnedValue>,
+}
+
+impl StateStore {
+    /// Creates a store for the interfaces at [`OBJECT_PATH`] on `conn`.
+    ///
+    /// The initial state is considered announced, as clients read it
+    /// when they first see the player.
+    pub fn new(conn: &Connection, media_player: MediaPlayer2State, player: PlayerState) -> zbus::Result<Self> {
+        Ok(Self {
+            conn: conn.clone(),
+            path: ObjectPath::from_static_str_unchecked(OBJECT_PATH).into(),
+            emitted_media_player: media_player.emitted_properties()?,
+            emitted_player: player.emitted_properties()?,
+            media_player,
+            player,
+        })
+    }
+
+    pub fn media_player(&self) -> &MediaPlayer2State {
+        &self.media_player
+    }
+
+    pub fn player(&self) -> &PlayerState {
+        &self.player
+    }
+
+    /// Stages changes to the `org.mpris.MediaPlayer2` properties, see [`flush`](Self::flush).
+    pub fn media_player_mut(&mut self) -> &mut MediaPlayer2State {
+        &mut self.media_player
+    }
+
+    /// Stages changes to the `org.mpris.MediaPlayer2.Player` properties, see [`flush`](Self::flush).
+    pub fn player_mut(&mut self) -> &mut PlayerState {
+        &mut self.player
+    }
+
+    /// Applies `update` to the `org.mpris.MediaPlayer2` properties and announces the changes.
+    pub async fn update_media_player(&mut self, update: impl FnOnce(&mut MediaPlayer2State)) -> zbus::Result<()> {
+        update(&mut self.media_player);
+        self.flush().await
+    }
+
+    /// Applies `update` to the `org.mpris.MediaPlayer2.Player` properties and announces the changes.
+    pub async fn update_player(&mut self, update: impl FnOnce(&mut PlayerState)) -> zbus::Result<()> {
+        update(&mut self.player);
+        self.flush().await
+    }
+
+    /// Emits `Seeked`, to be used whenever the position changes other than
+    /// by playing at the current rate.
+    pub async fn emit_seeked(&self, position: TimeInUs) -> zbus::Result<()> {
+        self.conn.emit_signal(None::<()>, &self.path, PLAYER_INTERFACE, "Seeked", &(position,)).await
+    }
+
+    /// Announces all properties that changed since the last flush.
+    pub async fn flush(&mut self) -> zbus::Result<()> {
+        self.flush_except(None).await
+    }
+
+    /// Like [`flush`](Self::flush), but leaves out the property `skipped`,
+    /// e.g. because the object server announces it after a client set it.
+    ///
+    /// No property name is used by both interfaces.
+    pub(crate) async fn flush_except(&mut self, skipped: Option<&str>) -> zbus::Result<()> {
+        let media_player = self.media_player.emitted_properties()?;
+        skip(&mut self.emitted_media_player, &media_player, skipped)?;
+        emit_changes(&self.conn, &self.path, MEDIA_PLAYER_INTERFACE, &self.emitted_media_player, &media_player).await?;
+        self.emitted_media_player = media_player;
+
+        let player = self.player.emitted_properties()?;
+        skip(&mut self.emitted_player, &player, skipped)?;
+        emit_changes(&self.conn, &self.path, PLAYER_INTERFACE, &self.emitted_player, &player).await?;
+        self.emitted_player = player;
+        Ok(())
+    }
+}
+
+/// Marks `skipped` as announced with its value in `current`.
+fn skip(
+    emitted: &mut HashMap<&'static str, OwnedValue>,
+    current: &HashMap<&'static str, OwnedValue>,
+    skipped: Option<&str>,
+) -> zbus::Result<()> {
+    if let Some((name, value)) = skipped.and_then(|it| current.get_key_value(it)) {
+        emitted.insert(name, value.try_clone()?);
+    }
+    Ok(())
+}
+
+async fn emit_changes(
+    conn: &Connection,
+    path: &OwnedObjectPath,
+    interface: &str,
+    previous: &HashMap<&'static str, OwnedValue>,
+    current: &HashMap<&'static str, OwnedValue>,
+) -> zbus::Result<()> {
+    let changed: HashMap<&str, &OwnedValue> = current.iter()
+        .filter(|(name, value)| previous.get(*name) != Some(value))
+        .map(|(name, value)| (*name, value))
+        .collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    conn.emit_signal(None::<()>, path, PROPERTIES_INTERFACE, "PropertiesChanged", &(interface, changed, Vec::<&str>::new())).await
+}
+
+fn owned<'v>(value: impl Into<Value<'v>>) -> zbus::Result<OwnedValue> {
+    Ok(value.into().try_to_owned()?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashMap};
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zbus::fdo::{IntrospectableProxy, PropertiesProxy};
+    use zbus::Connection;
+    use zvariant::Value;
+    use crate::server::engine::PlaybackEngine;
+    use crate::server::media_player::MediaPlayer2Server;
+    use crate::server::player::PlayerServer;
+    use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+    use crate::shared::{PlaybackStatus, OBJECT_PATH};
+
+    /// Properties of `interface` in `xml`, split into those announced by
+    /// `PropertiesChanged` and those annotated otherwise.
+    fn split_properties(xml: &str, interface: &str) -> (BTreeSet<String>, BTreeSet<String>) {
+        let (mut announced, mut silent) = (BTreeSet::new(), BTreeSet::new());
+        let start = xml.find(&format!(r#"<interface name="{interface}">"#)).expect("Interface not found");
+        let end = start + xml[start..].find("</interface>").unwrap();
+        let mut lines = xml[start..end].lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            let Some(rest) = line.strip_prefix(r#"<property name=""#) else {
+                continue;
+            };
+            let name = rest[..rest.find('"').unwrap()].to_string();
+            if line.ends_with("/>") {
+                announced.insert(name);
+            } else if lines.next().is_some_and(|it| it.contains("EmitsChangedSignal")) {
+                silent.insert(name);
+            } else {
+                announced.insert(name);
+            }
+        }
+        (announced, silent)
+    }
+
+    #[test(tokio::test)]
+    async fn announced_as_annotated() -> anyhow::Result<()> {
+        let server = Connection::session().await?;
+        let store = StateStore::new(&server, MediaPlayer2State::default(), PlayerState::default())?;
+        MediaPlayer2Server::serve(&server, PlayerServer::new(PlaybackEngine::default(), store)).await?;
+        let introspectable = IntrospectableProxy::builder(&server)
+            .destination(server.unique_name().unwrap().to_owned())?
+            .path(OBJECT_PATH)?
+            .build().await?;
+        let xml = introspectable.introspect().await?;
+
+        let (announced, silent) = split_properties(&xml, "org.mpris.MediaPlayer2");
+        let emitted: BTreeSet<String> = MediaPlayer2State::default().emitted_properties()?.into_keys().map(String::from).collect();
+        assert_eq!(emitted, announced);
+        assert!(silent.is_empty());
+
+        let (announced, silent) = split_properties(&xml, "org.mpris.MediaPlayer2.Player");
+        let emitted: BTreeSet<String> = PlayerState::default().emitted_properties()?.into_keys().map(String::from).collect();
+        assert_eq!(emitted, announced);
+        assert_eq!(silent, BTreeSet::from(["CanControl".to_string(), "Position".to_string()]));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn batched_changes() -> anyhow::Result<()> {
+        let server = Connection::session().await?;
+        let conn = Connection::session().await?;
+        let properties = PropertiesProxy::builder(&conn)
+            .destination(server.unique_name().unwrap().to_owned())?
+            .path(OBJECT_PATH)?
+            .build().await?;
+        let mut changes = properties.receive_properties_changed().await?;
+
+        let mut store = StateStore::new(&server, MediaPlayer2State::default(), PlayerState::default())?;
+        // Unchanged values and the position aren't announced
+        store.update_player(|player| {
+            player.position = 5_000_000;
+            player.can_control = false;
+            player.volume = 1.0;
+        }).await?;
+
+        store.player_mut().playback_status = PlaybackStatus::Playing;
+        store.player_mut().volume = 0.5;
+        store.player_mut().metadata.insert("xesam:title".to_string(), Value::from("Title").try_to_owned()?);
+        store.media_player_mut().identity = "zmpris".to_string();
+        store.flush().await?;
+
+        let signal = changes.next().await.expect("No PropertiesChanged");
+        let args = signal.args()?;
+        assert_eq!(args.interface_name().as_str(), "org.mpris.MediaPlayer2");
+        assert_eq!(args.changed_properties().keys().collect::<Vec<_>>(), [&"Identity"]);
+
+        let signal = changes.next().await.expect("No PropertiesChanged");
+        let args = signal.args()?;
+        assert_eq!(args.interface_name().as_str(), "org.mpris.MediaPlayer2.Player");
+        let mut changed: Vec<_> = args.changed_properties().keys().copied().collect();
+        changed.sort();
+        assert_eq!(changed, ["Metadata", "PlaybackStatus", "Volume"]);
+        assert_eq!(args.changed_properties()["PlaybackStatus"], Value::from("Playing"));
+        let metadata = HashMap::<String, Value>::try_from(args.changed_properties()["Metadata"].try_clone()?)?;
+        assert_eq!(metadata["xesam:title"], Value::from("Title"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use zbus::fdo;
+use zbus::interface;
+use zbus::object_server::SignalEmitter;
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use crate::shared::{TrackId, TrackIdExt, NO_TRACK};
+
+/// Default base path of generated track ids.
+pub const DEFAULT_ID_BASE: &str = "/zmpris/track";
+
+/// A track of a [`TrackListServer`].
+#[derive(Debug, PartialEq)]
+pub struct Track {
+    pub id: OwnedObjectPath,
+    /// Metadata of the track, always containing `mpris:trackid`.
+    pub metadata: HashMap<String, OwnedValue>,
+}
+
+/// An exported `org.mpris.MediaPlayer2.TrackList` backed by an in-memory queue.
+///
+/// Every added track gets a new id below the id base, so ids are unique
+/// even if the same URI is added twice. Tracks added by clients with
+/// `AddTrack` only carry `xesam:url`, use [`set_metadata`](Self::set_metadata)
+/// to fill in the rest.
+pub struct TrackListServer {
+    id_base: String,
+    next_id: u64,
+    tracks: Vec<Track>,
+    current: Option<OwnedObjectPath>,
+    can_edit_tracks: bool,
+}
+
+impl TrackListServer {
+    /// Creates an empty tracklist generating ids below `id_base`.
+    ///
+    /// Fails if `id_base` is not a valid object path or lies below
+    /// `/org/mpris`, which the specification reserves.
+    pub fn new(id_base: &str) -> zbus::Result<Self> {
+        let id_base = ObjectPath::try_from(id_base)?;
+        if id_base.as_str() == "/org/mpris" || id_base.starts_with("/org/mpris/") {
+            return Err(zbus::Error::Failure(format!("Track ids may not be below /org/mpris: {id_base}")));
+        }
+        Ok(Self {
+            id_base: id_base.trim_end_matches('/').to_string(),
+            next_id: 0,
+            tracks: Vec::new(),
+            current: None,
+            can_edit_tracks: true,
+        })
+    }
+
+    /// Whether clients may add and remove tracks, `true` by default.
+    pub fn with_can_edit_tracks(mut self, can_edit_tracks: bool) -> Self {
+        self.can_edit_tracks = can_edit_tracks;
+        self
+    }
+
+    /// The tracks, in tracklist order.
+    pub fn track_list(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Id of the current track, set by `GoTo` or when adding a track as current.
+    pub fn current(&self) -> Option<&OwnedObjectPath> {
+        self.current.as_ref()
+    }
+
+    /// Index of the track with the given id.
+    pub fn position(&self, id: &TrackId<'_>) -> Option<usize> {
+        self.tracks.iter().position(|it| it.id.as_ref() == *id)
+    }
+
+    /// Inserts a track after `after`, or at the start if it is
+    /// [`NO_TRACK`], and emits `TrackAdded`.
+    ///
+    /// Any `mpris:trackid` in `metadata` is replaced by the generated id.
+    /// The track is added even if emitting fails.
+    pub async fn add(
+        &mut self,
+        emitter: &SignalEmitter<'_>,
+        metadata: HashMap<String, OwnedValue>,
+        after: &TrackId<'_>,
+        set_as_current: bool,
+    ) -> zbus::Result<OwnedObjectPath> {
+        let index = match after.is_no_track() {
+            true => 0,
+            false => self.position(after)
+                .ok_or(fdo::Error::InvalidArgs(format!("Unknown track {after}")))? + 1,
+        };
+        let track = self.new_track(metadata)?;
+        let id = track.id.clone();
+        self.tracks.insert(index, track);
+        if set_as_current {
+            self.current = Some(id.clone());
+        }
+
+        Self::track_added(emitter, &self.tracks[index].metadata, after).await?;
+        self.tracks_invalidate(emitter).await?;
+        Ok(id)
+    }
+
+    /// Removes the track with the given id and emits `TrackRemoved`.
+    ///
+    /// Returns whether the track was found.
+    pub async fn remove(&mut self, emitter: &SignalEmitter<'_>, id: &TrackId<'_>) -> zbus::Result<bool> {
+        let Some(index) = self.position(id) else {
+            return Ok(false);
+        };
+        let track = self.tracks.remove(index);
+        if self.current.as_ref() == Some(&track.id) {
+            self.current = None;
+        }
+
+        Self::track_removed(emitter, &track.id).await?;
+        self.tracks_invalidate(emitter).await?;
+        Ok(true)
+    }
+
+    /// Replaces all tracks and emits `TrackListReplaced`.
+    ///
+    /// `current` is the index of the new current track.
+    pub async fn replace(
+        &mut self,
+        emitter: &SignalEmitter<'_>,
+        tracks: Vec<HashMap<String, OwnedValue>>,
+        current: Option<usize>,
+    ) -> zbus::Result<Vec<OwnedObjectPath>> {
+        self.tracks = tracks.into_iter()
+            .map(|it| self.new_track(it))
+            .collect::<zbus::Result<_>>()?;
+        self.current = current.and_then(|it| self.tracks.get(it)).map(|it| it.id.clone());
+        let ids: Vec<OwnedObjectPath> = self.tracks.iter().map(|it| it.id.clone()).collect();
+
+        let current = self.current.clone()
+            .unwrap_or_else(|| ObjectPath::from_static_str_unchecked(NO_TRACK).into());
+        Self::track_list_replaced(emitter, &ids, &current).await?;
+        self.tracks_invalidate(emitter).await?;
+        Ok(ids)
+    }
+
+    /// Replaces the metadata of the track with the given id and emits
+    /// `TrackMetadataChanged`.
+    ///
+    /// The track keeps its id. Returns whether the track was found.
+    pub async fn set_metadata(
+        &mut self,
+        emitter: &SignalEmitter<'_>,
+        id: &TrackId<'_>,
+        mut metadata: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<bool> {
+        let Some(index) = self.position(id) else {
+            return Ok(false);
+        };
+        let track = &mut self.tracks[index];
+        metadata.insert("mpris:trackid".to_string(), Value::from(track.id.clone()).try_to_owned()?);
+        track.metadata = metadata;
+
+        Self::track_metadata_changed(emitter, &track.id, &track.metadata).await?;
+        Ok(true)
+    }
+
+    fn new_track(&mut self, mut metadata: HashMap<String, OwnedValue>) -> zbus::Result<Track> {
+        self.next_id += 1;
+        let id: OwnedObjectPath = ObjectPath::try_from(format!("{}/{}", self.id_base, self.next_id))?.into();
+        metadata.insert("mpris:trackid".to_string(), Value::from(id.clone()).try_to_owned()?);
+        Ok(Track { id, metadata })
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackListServer {
+    /// Adds `uri` after `after_track`, has no effect if tracks can't be edited.
+    async fn add_track(
+        &mut self,
+        uri: &str,
+        after_track: ObjectPath<'_>,
+        set_as_current: bool,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        if !self.can_edit_tracks {
+            return Ok(());
+        }
+        let metadata = HashMap::from([("xesam:url".to_string(), Value::from(uri).try_to_owned().map_err(zbus::Error::Variant)?)]);
+        self.add(&emitter, metadata, &after_track, set_as_current).await?;
+        Ok(())
+    }
+
+    /// Metadata of the given tracks, unknown ids are skipped.
+    fn get_tracks_metadata(&self, track_ids: Vec<OwnedObjectPath>) -> fdo::Result<Vec<HashMap<String, OwnedValue>>> {
+        track_ids.iter()
+            .filter_map(|id| self.tracks.iter().find(|it| it.id == *id))
+            .map(|track| clone_metadata(&track.metadata))
+            .collect()
+    }
+
+    /// Makes `track_id` the current track, has no effect if it is unknown.
+    fn go_to(&mut self, track_id: ObjectPath<'_>) {
+        if self.position(&track_id).is_some() {
+            self.current = Some(track_id.into());
+        }
+    }
+
+    /// Removes `track_id`, has no effect if tracks can't be edited.
+    async fn remove_track(
+        &mut self,
+        track_id: ObjectPath<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        if self.can_edit_tracks {
+            self.remove(&emitter, &track_id).await?;
+        }
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn track_added(
+        emitter: &SignalEmitter<'_>,
+        metadata: &HashMap<String, OwnedValue>,
+        after_track: &ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn track_list_replaced(
+        emitter: &SignalEmitter<'_>,
+        tracks: &[OwnedObjectPath],
+        current_track: &ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn track_metadata_changed(
+        emitter: &SignalEmitter<'_>,
+        track_id: &ObjectPath<'_>,
+        metadata: &HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn track_removed(emitter: &SignalEmitter<'_>, track_id: &ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn can_edit_tracks(&self) -> bool {
+        self.can_edit_tracks
+    }
+
+    #[zbus(property(emits_changed_signal = "invalidates"))]
+    fn tracks(&self) -> Vec<OwnedObjectPath> {
+        self.tracks.iter().map(|it| it.id.clone()).collect()
+    }
+}
+
+fn clone_metadata(metadata: &HashMap<String, OwnedValue>) -> fdo::Result<HashMap<String, OwnedValue>> {
+    metadata.iter()
+        .map(|(key, value)| Ok((key.clone(), value.try_clone().map_err(zbus::Error::Variant)?)))
+        .collect()
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::collections::HashMap;
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zvariant::{ObjectPath, Value};
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::shared::{Metadata, NO_TRACK, OBJECT_PATH};
+    use crate::sync::TrackListProxy;
+    use crate::test_util::{proxy, serve};
+
+    #[test(tokio::test)]
+    async fn reject_reserved_id_base() -> anyhow::Result<()> {
+        assert!(TrackListServer::new("/org/mpris/MediaPlayer2/tracks").is_err());
+        assert!(TrackListServer::new("not a path").is_err());
+        assert!(TrackListServer::new("/org/mprisfoo").is_ok());
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn serve_track_list() -> anyhow::Result<()> {
+        let (server, iface) = serve(TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let proxy: TrackListProxy = proxy(&server).await?;
+        let mut added = proxy.receive_track_added().await?;
+        let mut removed = proxy.receive_track_removed().await?;
+        let mut metadata_changed = proxy.receive_track_metadata_changed().await?;
+        let mut replaced = proxy.receive_track_list_replaced().await?;
+        let properties = zbus::fdo::PropertiesProxy::builder(proxy.inner().connection())
+            .destination(server.unique_name().unwrap().to_owned())?
+            .path(OBJECT_PATH)?
+            .build().await?;
+        let mut properties_changed = properties.receive_properties_changed().await?;
+
+        let no_track = ObjectPath::from_static_str_unchecked(NO_TRACK);
+        proxy.add_track("file:///b", &no_track, false).await?;
+        proxy.add_track("file:///a", &no_track, true).await?;
+        proxy.add_track("file:///a", &no_track, false).await?;
+        let ids = proxy.tracks().await?;
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|it| it.starts_with("/zmpris/track/")));
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(iface.get().await.current().map(|it| it.as_ref()), Some(ids[1].clone()));
+
+        let signal = added.next().await.expect("No TrackAdded");
+        let args = signal.args()?;
+        let metadata = Metadata::from(args.metadata());
+        assert_eq!(metadata.url.as_deref(), Some("file:///b"));
+        assert_eq!(metadata.track_id.map(|it| it.to_string()), Some(ids[2].to_string()));
+        let signal = properties_changed.next().await.expect("No PropertiesChanged");
+        assert_eq!(signal.args()?.invalidated_properties().to_vec(), ["Tracks"]);
+
+        proxy.remove_track(&ids[0]).await?;
+        let signal = removed.next().await.expect("No TrackRemoved");
+        assert_eq!(*signal.args()?.track_id(), ids[0]);
+
+        let title = HashMap::from([("xesam:title".to_string(), Value::from("B").try_to_owned()?)]);
+        assert!(iface.get_mut().await.set_metadata(iface.signal_emitter(), &ids[2], title).await?);
+        let signal = metadata_changed.next().await.expect("No TrackMetadataChanged");
+        let args = signal.args()?;
+        let metadata = Metadata::from(args.metadata());
+        assert_eq!(metadata.title.as_deref(), Some("B"));
+        assert_eq!(metadata.track_id.map(|it| it.to_string()), Some(ids[2].to_string()));
+
+        let new_ids = iface.get_mut().await.replace(iface.signal_emitter(), vec![HashMap::new()], Some(0)).await?;
+        let signal = replaced.next().await.expect("No TrackListReplaced");
+        assert_eq!(signal.args()?.tracks().iter().map(|it| it.to_string()).collect::<Vec<_>>(), [new_ids[0].to_string()]);
+        assert_eq!(*signal.args()?.current_track(), *new_ids[0]);
+        assert!(!ids.contains(&new_ids[0]));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+use zbus::fdo::PeerProxy;
+use zbus::names::OwnedBusName;
+use zbus::proxy::CacheProperties;
+use zbus::Connection;
+use crate::runtime;
+use crate::shared::OBJECT_PATH;
+
+/// Time a player is given to answer during discovery, unless a timeout is
+/// passed explicitly.
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of a timeout-bounded discovery.
+///
+/// Players that failed to answer in time (or failed to answer at all)
+/// do not prevent the rest from being returned, their names are
+/// collected in [`unresponsive`](Self::unresponsive) instead.
+#[derive(Debug, Clone)]
+pub struct Discovered<T> {
+    /// Proxies of the players that responded in time.
+    pub proxies: Vec<T>,
+    /// Bus names of the players that did not respond in time.
+    pub unresponsive: Vec<OwnedBusName>,
+}
+
+impl<T> Default for Discovered<T> {
+    fn default() -> Self {
+        Self {
+            proxies: Vec::new(),
+            unresponsive: Vec::new(),
+        }
+    }
+}
+
+/// Pings all `names` concurrently with `org.freedesktop.DBus.Peer.Ping` and
+/// splits them into the ones that answered within `timeout` and the rest.
+#[cfg_attr(not(any(feature = "sync", feature = "blocking")), allow(dead_code))]
+pub(crate) async fn ping_all(
+    conn: &Connection,
+    names: Vec<OwnedBusName>,
+    timeout: Duration,
+) -> (Vec<OwnedBusName>, Vec<OwnedBusName>) {
+    let results = futures::future::join_all(
+        names.into_iter().map(|name| async move {
+            let result = runtime::timeout(timeout, ping(conn, &name)).await;
+            (name, result)
+        })
+    ).await;
+
+    let mut responsive = Vec::new();
+    let mut unresponsive = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(Ok(())) => responsive.push(name),
+            Ok(Err(e)) => {
+                log::debug!("Failed to ping {name}: {e}");
+                unresponsive.push(name);
+            }
+            Err(_) => {
+                log::debug!("Timed out pinging {name}");
+                unresponsive.push(name);
+            }
+        }
+    }
+    (responsive, unresponsive)
+}
+
+#[cfg_attr(not(any(feature = "sync", feature = "blocking")), allow(dead_code))]
+async fn ping(conn: &Connection, name: &OwnedBusName) -> zbus::Result<()> {
+    PeerProxy::builder(conn)
+        .destination(name.as_ref())?
+        .path(OBJECT_PATH)?
+        .cache_properties(CacheProperties::No)
+        .build().await?
+        .ping().await?;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use zvariant::{OwnedObjectPath, OwnedValue, Value};
+use crate::shared::TimeInUs;
+
+/// Typed view of the well-known entries of a metadata map.
+///
+/// See the [MPRIS metadata specification](https://www.freedesktop.org/wiki/Specifications/mpris-spec/metadata/).
+/// Entries that are missing or have an unexpected type are left empty,
+/// a single string is accepted where a list of strings is expected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// `mpris:trackid`, the unique identity of the track within the tracklist.
+    pub track_id: Option<OwnedObjectPath>,
+    /// `mpris:length`, the duration of the track in microseconds.
+    pub length: Option<TimeInUs>,
+    /// `mpris:artUrl`, the location of an image representing the track or album.
+    pub art_url: Option<String>,
+    /// `xesam:album`
+    pub album: Option<String>,
+    /// `xesam:albumArtist`
+    pub album_artist: Vec<String>,
+    /// `xesam:artist`
+    pub artist: Vec<String>,
+    /// `xesam:asText`, the track lyrics.
+    pub as_text: Option<String>,
+    /// `xesam:audioBPM`
+    pub audio_bpm: Option<i64>,
+    /// `xesam:autoRating`, an automatically-generated rating, between 0.0 and 1.0.
+    pub auto_rating: Option<f64>,
+    /// `xesam:comment`
+    pub comment: Vec<String>,
+    /// `xesam:composer`
+    pub composer: Vec<String>,
+    /// `xesam:contentCreated`, date/time in ISO 8601 format.
+    pub content_created: Option<String>,
+    /// `xesam:discNumber`
+    pub disc_number: Option<i64>,
+    /// `xesam:firstUsed`, date/time in ISO 8601 format.
+    pub first_used: Option<String>,
+    /// `xesam:genre`
+    pub genre: Vec<String>,
+    /// `xesam:lastUsed`, date/time in ISO 8601 format.
+    pub last_used: Option<String>,
+    /// `xesam:lyricist`
+    pub lyricist: Vec<String>,
+    /// `xesam:title`
+    pub title: Option<String>,
+    /// `xesam:trackNumber`
+    pub track_number: Option<i64>,
+    /// `xesam:url`, the location of the media file.
+    pub url: Option<String>,
+    /// `xesam:useCount`
+    pub use_count: Option<i64>,
+    /// `xesam:userRating`, a user-specified rating, between 0.0 and 1.0.
+    pub user_rating: Option<f64>,
+}
+
+impl Metadata {
+    fn from_lookup<'a>(get: impl Fn(&str) -> Option<&'a Value<'a>>) -> Self {
+        Self {
+            track_id: get("mpris:trackid").and_then(object_path),
+            length: get("mpris:length").and_then(integer),
+            art_url: get("mpris:artUrl").and_then(string),
+            album: get("xesam:album").and_then(string),
+            album_artist: get("xesam:albumArtist").map(strings).unwrap_or_default(),
+            artist: get("xesam:artist").map(strings).unwrap_or_default(),
+            as_text: get("xesam:asText").and_then(string),
+            audio_bpm: get("xesam:audioBPM").and_then(integer),
+            auto_rating: get("xesam:autoRating").and_then(float),
+            comment: get("xesam:comment").map(strings).unwrap_or_default(),
+            composer: get("xesam:composer").map(strings).unwrap_or_default(),
+            content_created: get("xesam:contentCreated").and_then(string),
+            disc_number: get("xesam:discNumber").and_then(integer),
+            first_used: get("xesam:firstUsed").and_then(string),
+            genre: get("xesam:genre").map(strings).unwrap_or_default(),
+            last_used: get("xesam:lastUsed").and_then(string),
+            lyricist: get("xesam:lyricist").map(strings).unwrap_or_default(),
+            title: get("xesam:title").and_then(string),
+            track_number: get("xesam:trackNumber").and_then(integer),
+            url: get("xesam:url").and_then(string),
+            use_count: get("xesam:useCount").and_then(integer),
+            user_rating: get("xesam:userRating").and_then(float),
+        }
+    }
+}
+
+impl From<&HashMap<String, OwnedValue>> for Metadata {
+    fn from(value: &HashMap<String, OwnedValue>) -> Self {
+        Self::from_lookup(|key| value.get(key).map(|it| &**it))
+    }
+}
+
+impl<'v> From<&'v HashMap<String, Value<'v>>> for Metadata {
+    fn from(value: &'v HashMap<String, Value<'v>>) -> Self {
+        Self::from_lookup(|key| value.get(key))
+    }
+}
+
+impl<'v> From<&'v HashMap<&str, Value<'v>>> for Metadata {
+    fn from(value: &'v HashMap<&str, Value<'v>>) -> Self {
+        Self::from_lookup(|key| value.get(key))
+    }
+}
+
+/// Reads the `mpris:trackid` entry of a metadata map.
+pub(crate) fn track_id(metadata: &HashMap<String, OwnedValue>) -> Option<OwnedObjectPath> {
+    object_path(metadata.get("mpris:trackid")?)
+}
+
+fn unwrap_variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        _ => value,
+    }
+}
+
+fn object_path(value: &Value<'_>) -> Option<OwnedObjectPath> {
+    match unwrap_variant(value) {
+        Value::ObjectPath(path) => Some(path.to_owned().into()),
+        // Some players send the track id as a plain string
+        Value::Str(path) => zvariant::ObjectPath::try_from(path.as_str()).ok().map(|it| it.to_owned().into()),
+        _ => None,
+    }
+}
+
+fn string(value: &Value<'_>) -> Option<String> {
+    match unwrap_variant(value) {
+        Value::Str(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn strings(value: &Value<'_>) -> Vec<String> {
+    match unwrap_variant(value) {
+        Value::Array(array) => array.iter().filter_map(string).collect(),
+        Value::Str(value) => vec![value.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+fn integer(value: &Value<'_>) -> Option<i64> {
+    match *unwrap_variant(value) {
+        Value::U8(value) => Some(value.into()),
+        Value::I16(value) => Some(value.into()),
+        Value::U16(value) => Some(value.into()),
+        Value::I32(value) => Some(value.into()),
+        Value::U32(value) => Some(value.into()),
+        Value::I64(value) => Some(value),
+        Value::U64(value) => i64::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+fn float(value: &Value<'_>) -> Option<f64> {
+    match *unwrap_variant(value) {
+        Value::F64(value) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::shared::Metadata;
+    use test_log::test;
+    use zvariant::{ObjectPath, Value};
+
+    #[test(tokio::test)]
+    async fn from_map() -> anyhow::Result<()> {
+        let map = HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(ObjectPath::try_from("/org/example/track/1")?)),
+            ("mpris:length".to_string(), Value::from(180_000_000u64)),
+            ("xesam:title".to_string(), Value::from("Title")),
+            ("xesam:artist".to_string(), Value::from(vec!["First", "Second"])),
+            ("xesam:albumArtist".to_string(), Value::from("Album Artist")),
+            ("xesam:trackNumber".to_string(), Value::from(3i32)),
+            ("xesam:userRating".to_string(), Value::from(true)),
+        ]);
+
+        let metadata = Metadata::from(&map);
+        assert_eq!(metadata.track_id.as_deref().map(|it| it.as_str()), Some("/org/example/track/1"));
+        assert_eq!(metadata.length, Some(180_000_000));
+        assert_eq!(metadata.title.as_deref(), Some("Title"));
+        assert_eq!(metadata.artist, vec!["First", "Second"]);
+        assert_eq!(metadata.album_artist, vec!["Album Artist"]);
+        assert_eq!(metadata.track_number, Some(3));
+        assert_eq!(metadata.user_rating, None);
+        assert_eq!(metadata.album, None);
+
+        anyhow::Ok(())
+    }
+}
@@ -1,19 +1,37 @@
+mod discovered;
 mod loop_status;
+mod metadata;
 mod playback_status;
+mod player_event;
 mod playlist_ordering;
 mod playlist_struct;
+mod properties;
 mod type_alias;
 
 use std::fmt::Display;
 use std::ops::Deref;
+pub use discovered::*;
 pub use loop_status::*;
+pub use metadata::*;
 pub use playback_status::*;
+pub use player_event::*;
 pub use playlist_ordering::*;
 pub use playlist_struct::*;
+pub use properties::*;
 pub use type_alias::*;
 
 pub const BASE_PATH: &str = "org.mpris.MediaPlayer2.";
 
+/// Object path every MPRIS interface is exported on.
+pub const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Name of the root MPRIS interface.
+pub const MEDIA_PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+pub const TRACK_LIST_INTERFACE: &str = "org.mpris.MediaPlayer2.TrackList";
+pub const PLAYLISTS_INTERFACE: &str = "org.mpris.MediaPlayer2.Playlists";
+pub(crate) const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
 /// Wrapper struct that allows to implement traits using foreign types
 #[derive(Debug, Clone, Copy)]
 pub struct W<T>(pub T);
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use zbus::fdo::PropertiesChanged;
+use zbus::Message;
+use zvariant::OwnedValue;
+use crate::player::Seeked;
+use crate::shared::{TimeInUs, PLAYER_INTERFACE};
+
+/// A change reported by a media player on the
+/// `org.mpris.MediaPlayer2.Player` interface.
+#[derive(Debug, PartialEq)]
+pub enum PlayerEvent {
+    /// `org.freedesktop.DBus.Properties.PropertiesChanged` was emitted.
+    PropertiesChanged {
+        /// Properties that changed, with their new values.
+        changed: HashMap<String, OwnedValue>,
+        /// Properties that changed, but whose values were not sent.
+        invalidated: Vec<String>,
+    },
+    /// `Seeked` was emitted with the new position.
+    Seeked(TimeInUs),
+}
+
+#[cfg_attr(not(any(feature = "sync", feature = "blocking")), allow(dead_code))]
+impl PlayerEvent {
+    /// Decodes a `PropertiesChanged` of the Player interface or a `Seeked` signal.
+    pub(crate) fn from_message(message: &Message) -> Option<Self> {
+        if let Some(signal) = PropertiesChanged::from_message(message.clone()) {
+            let args = signal.args().ok()?;
+            if args.interface_name() != PLAYER_INTERFACE {
+                return None;
+            }
+            let changed = args.changed_properties()
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.try_to_owned().ok()?)))
+                .collect();
+            let invalidated = args.invalidated_properties()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            return Some(Self::PropertiesChanged { changed, invalidated });
+        }
+
+        let signal = Seeked::from_message(message.clone())?;
+        Some(Self::Seeked(*signal.args().ok()?.position()))
+    }
+}
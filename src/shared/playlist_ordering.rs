@@ -25,6 +25,16 @@ pub enum PlaylistOrdering {
 }
 
 
+impl PlaylistOrdering {
+    /// Same as [`From<&str>`], but fails on orderings that aren't defined by
+    /// the specification instead of panicking.
+    pub fn from_name(value: &str) -> zbus::Result<Self> {
+        let ctx = Context::new_dbus(NATIVE_ENDIAN, 0);
+        let result = zvariant::to_bytes(ctx, value)?;
+        Ok(result.deserialize().map(|s| s.0)?)
+    }
+}
+
 impl FromStr for PlaylistOrdering {
     type Err = core::convert::Infallible;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -32,6 +42,8 @@ impl FromStr for PlaylistOrdering {
     }
 }
 
+/// Panics on orderings that aren't defined by the specification, see
+/// [`PlaylistOrdering::from_name`].
 impl From<&str> for PlaylistOrdering {
     fn from(value: &str) -> Self {
         let ctx = Context::new_dbus(NATIVE_ENDIAN, 0);
@@ -54,7 +66,7 @@ impl TryFrom<OwnedValue> for PlaylistOrdering {
     type Error = zbus::Error;
     fn try_from(value: OwnedValue) -> Result<Self, Self::Error> {
         match value.deref() {
-            Value::Str(value) => Ok(From::from(value.as_str())),
+            Value::Str(value) => PlaylistOrdering::from_name(value.as_str()),
             _ => Err(zbus::Error::Unsupported),
         }
     }
@@ -64,7 +76,7 @@ impl<'a> TryFrom<&Value<'a>> for PlaylistOrdering {
     type Error = zbus::Error;
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Str(value) => Ok(From::from(value.as_str())),
+            Value::Str(value) => PlaylistOrdering::from_name(value.as_str()),
             _ => Err(zbus::Error::Unsupported),
         }
     }
@@ -107,7 +119,9 @@ mod tests {
         for (status, string) in items {
             assert_eq!(String::from(status), string);
             assert_eq!(status, PlaylistOrdering::from(string));
+            assert_eq!(status, PlaylistOrdering::from_name(string)?);
         }
+        assert!(PlaylistOrdering::from_name("Random").is_err());
 
         anyhow::Ok(())
     }
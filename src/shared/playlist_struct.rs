@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use zvariant::{OwnedObjectPath, OwnedValue, Type, Value};
 use crate::shared::W;
 
+/// A playlist as returned by the `Playlists` interface.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Type, Value, OwnedValue)]
 pub struct Playlist {
     playlist_id: OwnedObjectPath,
@@ -9,6 +10,29 @@ pub struct Playlist {
     uri: String,
 }
 
+impl Playlist {
+    pub fn new(playlist_id: OwnedObjectPath, name: String, uri: String) -> Self {
+        Self { playlist_id, name, uri }
+    }
+
+    /// A unique identifier for the playlist.
+    ///
+    /// This should remain the same if the playlist is renamed.
+    pub fn id(&self) -> &OwnedObjectPath {
+        &self.playlist_id
+    }
+
+    /// The name of the playlist, typically given by the user.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The URI of an (optional) icon, empty if there is none.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Type, Value, OwnedValue)]
 struct MaybePlaylist {
     has_value: bool,
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use zvariant::OwnedValue;
+use crate::shared::{LoopStatus, PlaybackRate, PlaybackStatus, TimeInUs, Volume};
+
+/// Properties of the `org.mpris.MediaPlayer2` interface.
+///
+/// Decoded from the reply of a single
+/// `org.freedesktop.DBus.Properties.GetAll` call.
+/// Properties the specification marks as optional are [`None`] when the
+/// media player does not expose them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlayer2Properties {
+    pub can_quit: bool,
+    pub fullscreen: Option<bool>,
+    pub can_set_fullscreen: Option<bool>,
+    pub can_raise: bool,
+    pub has_track_list: bool,
+    pub identity: String,
+    pub desktop_entry: Option<String>,
+    pub supported_uri_schemes: Vec<String>,
+    pub supported_mime_types: Vec<String>,
+}
+
+/// Properties of the `org.mpris.MediaPlayer2.Player` interface.
+///
+/// Decoded from the reply of a single
+/// `org.freedesktop.DBus.Properties.GetAll` call.
+/// Properties the specification marks as optional are [`None`] when the
+/// media player does not expose them.
+#[derive(Debug, PartialEq)]
+pub struct PlayerProperties {
+    pub playback_status: PlaybackStatus,
+    pub loop_status: Option<LoopStatus>,
+    pub rate: PlaybackRate,
+    pub shuffle: Option<bool>,
+    pub metadata: HashMap<String, OwnedValue>,
+    pub volume: Volume,
+    pub position: TimeInUs,
+    pub minimum_rate: PlaybackRate,
+    pub maximum_rate: PlaybackRate,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+    pub can_play: bool,
+    pub can_pause: bool,
+    pub can_seek: bool,
+    pub can_control: bool,
+}
+
+/// State of both main MPRIS interfaces of a single media player.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    pub media_player: MediaPlayer2Properties,
+    pub player: PlayerProperties,
+}
+
+impl TryFrom<HashMap<String, OwnedValue>> for MediaPlayer2Properties {
+    type Error = zbus::Error;
+    fn try_from(mut value: HashMap<String, OwnedValue>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            can_quit: required(&mut value, "CanQuit")?,
+            fullscreen: optional(&mut value, "Fullscreen")?,
+            can_set_fullscreen: optional(&mut value, "CanSetFullscreen")?,
+            can_raise: required(&mut value, "CanRaise")?,
+            has_track_list: required(&mut value, "HasTrackList")?,
+            identity: required(&mut value, "Identity")?,
+            desktop_entry: optional(&mut value, "DesktopEntry")?,
+            supported_uri_schemes: required(&mut value, "SupportedUriSchemes")?,
+            supported_mime_types: required(&mut value, "SupportedMimeTypes")?,
+        })
+    }
+}
+
+impl TryFrom<HashMap<String, OwnedValue>> for PlayerProperties {
+    type Error = zbus::Error;
+    fn try_from(mut value: HashMap<String, OwnedValue>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            playback_status: required(&mut value, "PlaybackStatus")?,
+            loop_status: optional(&mut value, "LoopStatus")?,
+            rate: required(&mut value, "Rate")?,
+            shuffle: optional(&mut value, "Shuffle")?,
+            metadata: required(&mut value, "Metadata")?,
+            volume: required(&mut value, "Volume")?,
+            position: required(&mut value, "Position")?,
+            minimum_rate: required(&mut value, "MinimumRate")?,
+            maximum_rate: required(&mut value, "MaximumRate")?,
+            can_go_next: required(&mut value, "CanGoNext")?,
+            can_go_previous: required(&mut value, "CanGoPrevious")?,
+            can_play: required(&mut value, "CanPlay")?,
+            can_pause: required(&mut value, "CanPause")?,
+            can_seek: required(&mut value, "CanSeek")?,
+            can_control: required(&mut value, "CanControl")?,
+        })
+    }
+}
+
+fn optional<T>(properties: &mut HashMap<String, OwnedValue>, name: &'static str) -> zbus::Result<Option<T>>
+where
+    T: TryFrom<OwnedValue>,
+    T::Error: Into<zbus::Error>,
+{
+    properties.remove(name)
+        .map(T::try_from)
+        .transpose()
+        .map_err(Into::into)
+}
+
+fn required<T>(properties: &mut HashMap<String, OwnedValue>, name: &'static str) -> zbus::Result<T>
+where
+    T: TryFrom<OwnedValue>,
+    T::Error: Into<zbus::Error>,
+{
+    optional(properties, name)?.ok_or(zbus::Error::MissingParameter(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::shared::{LoopStatus, MediaPlayer2Properties, PlaybackStatus, PlayerProperties};
+    use test_log::test;
+    use zvariant::{OwnedValue, Value};
+
+    fn owned(value: Value<'_>) -> OwnedValue {
+        value.try_to_owned().expect("Failed to convert value")
+    }
+
+    #[test(tokio::test)]
+    async fn media_player_properties() -> anyhow::Result<()> {
+        let properties = HashMap::from([
+            ("CanQuit".to_string(), owned(Value::from(true))),
+            ("CanRaise".to_string(), owned(Value::from(false))),
+            ("HasTrackList".to_string(), owned(Value::from(false))),
+            ("Identity".to_string(), owned(Value::from("VLC media player"))),
+            ("DesktopEntry".to_string(), owned(Value::from("vlc"))),
+            ("SupportedUriSchemes".to_string(), owned(Value::from(vec!["file", "http"]))),
+            ("SupportedMimeTypes".to_string(), owned(Value::from(vec!["audio/mpeg"]))),
+        ]);
+
+        let result = MediaPlayer2Properties::try_from(properties)?;
+        assert_eq!(result.identity, "VLC media player");
+        assert_eq!(result.desktop_entry.as_deref(), Some("vlc"));
+        assert_eq!(result.fullscreen, None);
+        assert_eq!(result.supported_uri_schemes, vec!["file", "http"]);
+
+        anyhow::Ok(())
+    }
+
+    fn player_map() -> HashMap<String, OwnedValue> {
+        HashMap::from([
+            ("PlaybackStatus".to_string(), owned(Value::from("Playing"))),
+            ("LoopStatus".to_string(), owned(Value::from("Track"))),
+            ("Rate".to_string(), owned(Value::from(1.0))),
+            ("Metadata".to_string(), owned(Value::from(HashMap::<String, Value>::new()))),
+            ("Volume".to_string(), owned(Value::from(0.5))),
+            ("Position".to_string(), owned(Value::from(42i64))),
+            ("MinimumRate".to_string(), owned(Value::from(1.0))),
+            ("MaximumRate".to_string(), owned(Value::from(1.0))),
+            ("CanGoNext".to_string(), owned(Value::from(true))),
+            ("CanGoPrevious".to_string(), owned(Value::from(true))),
+            ("CanPlay".to_string(), owned(Value::from(true))),
+            ("CanPause".to_string(), owned(Value::from(true))),
+            ("CanSeek".to_string(), owned(Value::from(false))),
+            ("CanControl".to_string(), owned(Value::from(true))),
+        ])
+    }
+
+    #[test(tokio::test)]
+    async fn player_properties() -> anyhow::Result<()> {
+        let result = PlayerProperties::try_from(player_map())?;
+        assert_eq!(result.playback_status, PlaybackStatus::Playing);
+        assert_eq!(result.loop_status, Some(LoopStatus::Track));
+        assert_eq!(result.shuffle, None);
+        assert_eq!(result.position, 42);
+
+        let mut properties = player_map();
+        properties.remove("CanControl");
+        let result = PlayerProperties::try_from(properties);
+        assert!(matches!(result, Err(zbus::Error::MissingParameter("CanControl"))));
+
+        anyhow::Ok(())
+    }
+}
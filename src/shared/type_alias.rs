@@ -44,12 +44,15 @@ pub type PlaybackRate = f64;
 /// A unique resource identifier.
 pub type Uri<'a> = &'a str;
 
+/// Track id meaning "no track", e.g. to insert at the start of the tracklist.
+pub const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
 pub trait TrackIdExt {
     fn is_no_track(&self) -> bool;
 }
 
 impl TrackIdExt for TrackId<'_> {
     fn is_no_track(&self) -> bool {
-        self.eq("/org/mpris/MediaPlayer2/TrackList/NoTrack")
+        self.eq(NO_TRACK)
     }
 }
\ No newline at end of file
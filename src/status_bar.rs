@@ -0,0 +1,250 @@
+use crate::json::{self, ObjectWriter};
+use crate::shared::{PlaybackStatus, TimeInUs, BASE_PATH};
+use crate::sync::follow::PlayerStatus;
+use crate::sync::PlayerProxy;
+
+/// Header line of the i3bar protocol, announcing click events on stdin.
+pub const I3BAR_HEADER: &str = r#"{"version":1,"click_events":true}"#;
+
+/// A JSON protocol of status bars.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum BarProtocol {
+    /// One object per line, as read by waybar custom modules with `"return-type": "json"`.
+    Waybar,
+    /// The i3bar protocol of i3bar and swaybar: a header, then an endless
+    /// array of status lines.
+    I3bar,
+}
+
+impl BarProtocol {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "waybar" => Some(Self::Waybar),
+            "i3bar" => Some(Self::I3bar),
+            _ => None,
+        }
+    }
+}
+
+/// What a status bar shows of a player.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BarStatus {
+    pub text: String,
+    pub tooltip: String,
+    /// CSS class, see [`class`].
+    pub class: String,
+    /// Progress through the track, if the position and length are known.
+    pub percentage: Option<u8>,
+}
+
+impl BarStatus {
+    /// Formats `status` with the `text` and `tooltip` templates, see [`format`].
+    pub fn new(status: &PlayerStatus, text: &str, tooltip: &str) -> Self {
+        let percentage = match (status.position, status.metadata.length) {
+            (Some(position), Some(length)) if length > 0 => Some((position.clamp(0, length) * 100 / length) as u8),
+            _ => None,
+        };
+
+        Self {
+            text: format(status, text),
+            tooltip: format(status, tooltip),
+            class: class(status.playback_status).to_string(),
+            percentage,
+        }
+    }
+
+    /// Shown while there is no player, which hides waybar modules.
+    pub fn empty() -> Self {
+        Self {
+            class: "none".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// A line for waybar.
+    pub fn to_waybar(&self) -> String {
+        let json = ObjectWriter::new()
+            .string("text", &self.text)
+            .string("tooltip", &self.tooltip)
+            .string("class", &self.class);
+        match self.percentage {
+            Some(percentage) => json.number("percentage", percentage.into()),
+            None => json,
+        }.finish()
+    }
+
+    /// A block of the i3bar protocol called `name`.
+    ///
+    /// The tooltip, class and percentage are passed in the custom `_tooltip`,
+    /// `_class` and `_percentage` keys.
+    pub fn to_i3bar(&self, name: &str) -> String {
+        let json = ObjectWriter::new()
+            .string("name", name)
+            .string("full_text", &self.text)
+            .string("_tooltip", &self.tooltip)
+            .string("_class", &self.class);
+        match self.percentage {
+            Some(percentage) => json.number("_percentage", percentage.into()),
+            None => json,
+        }.finish()
+    }
+}
+
+/// Fills the placeholders of `template` with `status`.
+///
+/// The placeholders are `{player}`, `{status}`, `{artist}`, `{title}`,
+/// `{album}`, `{volume}` in percent, and `{position}` and `{length}` as
+/// `m:ss`. Missing values are left empty, unknown placeholders as is.
+pub fn format(status: &PlayerStatus, template: &str) -> String {
+    let mut line = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        line.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match placeholder(status, &rest[1..end]) {
+            Some(value) => line.push_str(&value),
+            None => line.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    line.push_str(rest);
+    line
+}
+
+fn placeholder(status: &PlayerStatus, name: &str) -> Option<String> {
+    let value = match name {
+        "player" => status.player.strip_prefix(BASE_PATH).unwrap_or(&status.player).to_string(),
+        "status" => String::from(status.playback_status),
+        "artist" => status.metadata.artist.join(", "),
+        "title" => status.metadata.title.clone().unwrap_or_default(),
+        "album" => status.metadata.album.clone().unwrap_or_default(),
+        "volume" => format!("{:.0}", status.volume * 100.0),
+        "position" => status.position.map(format_time).unwrap_or_default(),
+        "length" => status.metadata.length.map(format_time).unwrap_or_default(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn format_time(time: TimeInUs) -> String {
+    let seconds = time.max(0) / 1_000_000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// The CSS class of `status`: `playing`, `paused` or `stopped`.
+pub fn class(status: PlaybackStatus) -> &'static str {
+    match status {
+        PlaybackStatus::Playing => "playing",
+        PlaybackStatus::Paused => "paused",
+        PlaybackStatus::Stopped => "stopped",
+    }
+}
+
+/// What clicking a status bar module does.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum ClickAction {
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl ClickAction {
+    /// Left click toggles playback, middle click goes to the previous and
+    /// right click to the next track.
+    pub fn from_button(button: u32) -> Option<Self> {
+        match button {
+            1 => Some(Self::PlayPause),
+            2 => Some(Self::Previous),
+            3 => Some(Self::Next),
+            _ => None,
+        }
+    }
+
+    pub async fn apply(self, proxy: &PlayerProxy<'_>) -> zbus::Result<()> {
+        match self {
+            Self::PlayPause => proxy.play_pause().await,
+            Self::Next => proxy.next().await,
+            Self::Previous => proxy.previous().await,
+        }
+    }
+}
+
+/// Reads the button of a click event sent by i3bar on stdin.
+///
+/// Events are objects in an endless array, so the line may start with `[` or `,`.
+pub fn parse_i3bar_click(line: &str) -> Option<u32> {
+    let line = line.trim_start();
+    let event = line.strip_prefix(['[', ',']).unwrap_or(line);
+    json::parse(event)?.get("button")?.as_u64()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::shared::{Metadata, PlaybackStatus};
+    use crate::status_bar::{format, parse_i3bar_click, BarStatus, ClickAction};
+    use crate::sync::follow::PlayerStatus;
+
+    #[test]
+    fn format_status() {
+        let status = PlayerStatus {
+            player: "org.mpris.MediaPlayer2.vlc".to_string(),
+            playback_status: PlaybackStatus::Playing,
+            metadata: Metadata {
+                artist: vec!["Artist".to_string(), "Guest".to_string()],
+                title: Some("Title".to_string()),
+                length: Some(185_000_000),
+                ..Default::default()
+            },
+            volume: 0.5,
+            position: Some(61_500_000),
+        };
+
+        assert_eq!(
+            format(&status, "{player}: {status} {artist} - {title} [{album}] {position}/{length} {volume}% {unknown"),
+            "vlc: Playing Artist, Guest - Title [] 1:01/3:05 50% {unknown",
+        );
+        assert_eq!(format(&status, "{other} {title}"), "{other} Title");
+    }
+
+    #[test]
+    fn bar_json() {
+        let status = PlayerStatus {
+            player: "org.mpris.MediaPlayer2.vlc".to_string(),
+            playback_status: PlaybackStatus::Paused,
+            metadata: Metadata {
+                artist: vec!["Artist".to_string()],
+                title: Some("\"Quoted\"\tTitle".to_string()),
+                length: Some(200_000_000),
+                ..Default::default()
+            },
+            volume: 1.0,
+            position: Some(50_000_000),
+        };
+
+        let bar = BarStatus::new(&status, "{title}", "{artist}\n{player}");
+        assert_eq!(
+            bar.to_waybar(),
+            r#"{"text":"\"Quoted\"\tTitle","tooltip":"Artist\nvlc","class":"paused","percentage":25}"#,
+        );
+        assert_eq!(
+            bar.to_i3bar("zmpris"),
+            r#"{"name":"zmpris","full_text":"\"Quoted\"\tTitle","_tooltip":"Artist\nvlc","_class":"paused","_percentage":25}"#,
+        );
+        assert_eq!(BarStatus::empty().to_waybar(), r#"{"text":"","tooltip":"","class":"none"}"#);
+    }
+
+    #[test]
+    fn click_events() {
+        assert_eq!(parse_i3bar_click(r#"[{"name":"zmpris","button":1,"x":10}"#), Some(1));
+        assert_eq!(parse_i3bar_click(r#",{"name":"zmpris","button" : 3}"#), Some(3));
+        assert_eq!(parse_i3bar_click("["), None);
+        // Only the button of the event counts
+        assert_eq!(parse_i3bar_click(r#"[{"name":"\"button\":2","instance":"x"}"#), None);
+        assert_eq!(parse_i3bar_click(r#",{"name":"zmpris","button":1.5}"#), None);
+        assert_eq!(ClickAction::from_button(2), Some(ClickAction::Previous));
+        assert_eq!(ClickAction::from_button(4), None);
+    }
+}
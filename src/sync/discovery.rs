@@ -1,29 +1,145 @@
 #![allow(dead_code)]
 
+use std::time::Duration;
 use anyhow::{anyhow, bail, Result};
-use zbus::proxy::ProxyImpl;
+use zbus::fdo::PropertiesProxy;
+use zbus::names::OwnedBusName;
+use zbus::proxy::{CacheProperties, ProxyImpl};
 use zbus::{Connection, Proxy};
 use futures::stream::StreamExt;
-use crate::shared::{PlaybackStatus, BASE_PATH};
+use crate::runtime;
+use crate::shared::{ping_all, Discovered, PlaybackStatus, BASE_PATH, DISCOVERY_TIMEOUT};
 
+/// Proxies of all players that answer within [`DISCOVERY_TIMEOUT`].
 pub async fn all<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>>
 >(conn: &Connection) -> Result<Vec<T>> {
+    Ok(all_with_timeout(conn, DISCOVERY_TIMEOUT).await?.proxies)
+}
+
+/// Same as [`all`], but the proxies are built with [`CacheProperties::No`].
+///
+/// Such proxies neither subscribe to `PropertiesChanged` nor fetch the
+/// properties up front, which is cheaper for one-shot commands. Every
+/// property read is a round-trip, and `receive_*_changed` streams are not
+/// available, use [`upgrade`] to turn a proxy into a cached one.
+pub async fn all_uncached<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection) -> Result<Vec<T>> {
+    Ok(all_in(conn, BASE_PATH, DISCOVERY_TIMEOUT, CacheProperties::No).await?.proxies)
+}
+
+/// Rebuilds `proxy` for the same destination and path with the default
+/// property caching, e.g. to watch a player found with [`all_uncached`].
+pub async fn upgrade<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(proxy: &T) -> zbus::Result<T> {
+    let inner = proxy.inner();
+    T::builder(inner.connection())
+        .destination(inner.destination().to_owned())?
+        .path(inner.path().to_owned())?
+        .cache_properties(CacheProperties::default())
+        .build().await
+}
+
+/// Builds a `org.freedesktop.DBus.Properties` proxy for the object of `proxy`,
+/// without caching, so every read is current.
+pub(crate) async fn properties(proxy: &Proxy<'_>) -> zbus::Result<PropertiesProxy<'static>> {
+    PropertiesProxy::builder(proxy.connection())
+        .destination(proxy.destination().to_owned())?
+        .path(proxy.path().to_owned())?
+        .cache_properties(CacheProperties::No)
+        .build().await
+}
+
+/// Same as [`all`], but every player is given at most `timeout` to answer
+/// a `org.freedesktop.DBus.Peer.Ping`.
+///
+/// Players are pinged concurrently, so a single hung player only delays the
+/// result by `timeout` and ends up in [`Discovered::unresponsive`].
+pub async fn all_with_timeout<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, timeout: Duration) -> Result<Discovered<T>> {
+    all_in(conn, BASE_PATH, timeout, CacheProperties::default()).await
+}
+
+/// Waits until `name` is owned, or any player appears if [`None`], and
+/// returns the name.
+///
+/// Returns immediately if the name is already owned.
+pub async fn wait_for(conn: &Connection, name: Option<&str>) -> zbus::Result<OwnedBusName> {
+    wait_for_in(conn, BASE_PATH, name).await
+}
+
+async fn wait_for_in(conn: &Connection, namespace: &str, name: Option<&str>) -> zbus::Result<OwnedBusName> {
+    let dbus = zbus::fdo::DBusProxy::new(conn).await?;
+    let matches = |it: &str| match name {
+        Some(name) => it == name,
+        None => it.starts_with(namespace),
+    };
+
+    // Subscribed before listing the names, so a player appearing in between isn't missed
+    let mut changes = dbus.receive_name_owner_changed().await?;
+    if let Some(found) = dbus.list_names().await?.into_iter().find(|it| matches(it)) {
+        return Ok(found);
+    }
+    while let Some(signal) = changes.next().await {
+        let args = signal.args()?;
+        if args.new_owner().is_some() && matches(args.name()) {
+            return Ok(args.name().to_owned().into());
+        }
+    }
+    Err(zbus::Error::Failure("Name owner changes ended".to_string()))
+}
+
+async fn player_names(conn: &Connection, namespace: &str) -> zbus::Result<Vec<OwnedBusName>> {
     let dbus = zbus::fdo::DBusProxy::new(conn).await?;
     let names = dbus.list_names().await?
-        .iter()
-        .filter(|it| it.starts_with(BASE_PATH))
-        .cloned()
-        .collect::<Vec<_>>();
-    let mut proxies: Vec<T> = Vec::new();
-    for name in names {
-        let proxy = T::builder(conn)
-            .destination(name)?
-            .build();
-        proxies.push(proxy.await?);
+        .into_iter()
+        .filter(|it| it.starts_with(namespace))
+        .collect();
+    Ok(names)
+}
+
+/// Discovers the players owning a name starting with `namespace`.
+async fn all_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, namespace: &str, timeout: Duration, cache: CacheProperties) -> anyhow::Result<Discovered<T>> {
+    let names = player_names(conn, namespace).await?;
+    Ok(discover(conn, names, timeout, cache).await)
+}
+
+async fn discover<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, names: Vec<OwnedBusName>, timeout: Duration, cache: CacheProperties) -> Discovered<T> {
+    let (responsive, unresponsive) = ping_all(conn, names, timeout).await;
+    let mut discovered = Discovered { proxies: Vec::new(), unresponsive };
+    for name in responsive {
+        match build::<T>(conn, name.clone(), cache).await {
+            Ok(proxy) => discovered.proxies.push(proxy),
+            Err(e) => {
+                log::debug!("Failed to build proxy for {name}: {e}");
+                discovered.unresponsive.push(name);
+            }
+        }
     }
-    Ok(proxies)
+    discovered
+}
+
+async fn build<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, name: OwnedBusName, cache: CacheProperties) -> zbus::Result<T> {
+    T::builder(conn)
+        .destination(name)?
+        .cache_properties(cache)
+        .build().await
 }
 
 pub async fn by_name<
@@ -41,15 +157,17 @@ pub async fn first<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>>
 >(conn: &Connection) -> Result<T> {
-    let dbus = zbus::fdo::DBusProxy::new(conn).await?;
-    let Some(name) = dbus.list_names().await?
-        .iter().find(|it| it.starts_with(BASE_PATH))
-        .cloned() else {
+    first_in(conn, BASE_PATH).await
+}
+
+async fn first_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>>
+>(conn: &Connection, namespace: &str) -> Result<T> {
+    let Some(name) = player_names(conn, namespace).await?.into_iter().next() else {
         bail!("No MPRIS2 instances found.");
     };
-    let proxy: T = T::builder(conn)
-        .destination(name)?
-        .build().await?;
+    let proxy: T = build(conn, name, CacheProperties::default()).await?;
 
     Ok(proxy)
 }
@@ -58,7 +176,14 @@ pub async fn currently_playing<
     'a,
     T: ProxyImpl<'a> + From<Proxy<'a>> + Clone
 >(conn: &Connection) -> Result<T> {
-    let proxies: Vec<T> = all(conn).await?;
+    currently_playing_in(conn, BASE_PATH).await
+}
+
+async fn currently_playing_in<
+    'a,
+    T: ProxyImpl<'a> + From<Proxy<'a>> + Clone
+>(conn: &Connection, namespace: &str) -> Result<T> {
+    let proxies: Vec<T> = all_in(conn, namespace, DISCOVERY_TIMEOUT, CacheProperties::default()).await?.proxies;
 
     let proxy = futures::stream::iter(&proxies)
         .filter(|it| Box::pin(is_playback_status(it.inner(), PlaybackStatus::Playing)))
@@ -72,8 +197,9 @@ pub async fn currently_playing<
 
 async fn is_playback_status(it: &Proxy<'_>, playback_status: PlaybackStatus) -> bool {
     let result: Result<bool> = async {
-        let status = PlaybackStatus::from(it.get_property::<String>("PlaybackStatus").await?.as_str()) == playback_status;
-        Ok(status)
+        let status = runtime::timeout(DISCOVERY_TIMEOUT, it.get_property::<String>("PlaybackStatus")).await
+            .map_err(|_| anyhow!("Timed out reading PlaybackStatus"))??;
+        Ok(PlaybackStatus::from(status.as_str()) == playback_status)
     }.await;
     result.unwrap_or(false)
 }
@@ -83,33 +209,78 @@ async fn is_playback_status(it: &Proxy<'_>, playback_status: PlaybackStatus) ->
 mod test {
     use anyhow::anyhow;
     use log::info;
+    use std::time::Duration;
+    use zbus::names::OwnedBusName;
+    use zbus::proxy::CacheProperties;
     use crate::media_player::MediaPlayer2Proxy;
+    use crate::player::PlayerProxy;
+    use crate::shared::BASE_PATH;
+    use crate::sync::discovery::*;
+    use crate::test_util::serve_named_player;
     use test_log::test;
 
+    /// Names of the players of each test start with their own namespace,
+    /// so tests running in parallel don't discover each other's players.
+    fn namespace(test: &str) -> String {
+        format!("{BASE_PATH}zmpris_test.sync_{test}.")
+    }
+
     #[test(tokio::test)]
     async fn get_all_players() -> anyhow::Result<()> {
+        let namespace = namespace("all");
+        let _player = serve_named_player(&format!("{namespace}player"), false).await?;
         let conn = zbus::Connection::session().await?;
 
-        let _: Vec<MediaPlayer2Proxy> = crate::sync::discovery::all(&conn).await?;
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, DISCOVERY_TIMEOUT, CacheProperties::default()).await?;
+        assert_eq!(discovered.proxies.len(), 1);
+        assert_eq!(discovered.proxies[0].identity().await?, format!("{namespace}player"));
 
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn get_first_player() -> anyhow::Result<()> {
+    async fn get_all_players_uncached() -> anyhow::Result<()> {
+        let namespace = namespace("all_uncached");
+        let _player = serve_named_player(&format!("{namespace}player"), false).await?;
         let conn = zbus::Connection::session().await?;
 
-        let result = crate::sync::discovery::first::<MediaPlayer2Proxy>(&conn).await;
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, DISCOVERY_TIMEOUT, CacheProperties::No).await?;
+        assert_eq!(discovered.proxies.len(), 1);
+        let proxy = upgrade(&discovered.proxies[0]).await?;
+        info!("Player identity: {:?}", proxy.identity().await?);
 
-        match result {
-            Err(e) => {
-                assert!(e.to_string().eq("No MPRIS2 instances found."));
-            }
-            Ok(proxy) => {
-                info!("Player identity: {:?}", proxy.identity().await?);
-            }
-        };
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn get_all_players_with_timeout() -> anyhow::Result<()> {
+        let namespace = namespace("all_with_timeout");
+        let hung_name = format!("{namespace}hung");
+        // Never serves any object, so the ping goes unanswered
+        let hung = zbus::Connection::session().await?;
+        hung.request_name(hung_name.as_str()).await?;
+        let _player = serve_named_player(&format!("{namespace}player"), false).await?;
+        let conn = zbus::Connection::session().await?;
 
+        let discovered: Discovered<MediaPlayer2Proxy> = all_in(&conn, &namespace, Duration::from_millis(200), CacheProperties::default()).await?;
+        assert_eq!(discovered.unresponsive, [OwnedBusName::try_from(hung_name)?]);
+        assert_eq!(discovered.proxies.len(), 1);
+        assert_eq!(discovered.proxies[0].identity().await?, format!("{namespace}player"));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn get_first_player() -> anyhow::Result<()> {
+        let namespace = namespace("first");
+        let conn = zbus::Connection::session().await?;
+
+        let result = first_in::<MediaPlayer2Proxy>(&conn, &namespace).await;
+        assert_eq!(result.err().map(|e| e.to_string()).as_deref(), Some("No MPRIS2 instances found."));
+
+        let _player = serve_named_player(&format!("{namespace}player"), false).await?;
+        let proxy = first_in::<MediaPlayer2Proxy>(&conn, &namespace).await?;
+        assert_eq!(proxy.identity().await?, format!("{namespace}player"));
 
         Ok(())
     }
@@ -118,7 +289,7 @@ mod test {
     async fn get_player_by_name() -> anyhow::Result<()> {
         let conn = zbus::Connection::session().await?;
 
-        let result = crate::sync::discovery::by_name::<MediaPlayer2Proxy>(&conn, "com.example.application").await;
+        let result = by_name::<MediaPlayer2Proxy>(&conn, "com.example.application").await;
 
         match result {
             Err(e) => {
@@ -136,21 +307,41 @@ mod test {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn wait_for_name() -> anyhow::Result<()> {
+        let namespace = namespace("wait_for");
+        let name = format!("{namespace}player");
+        let conn = zbus::Connection::session().await?;
+        let player = zbus::Connection::session().await?;
+
+        let (found, requested) = futures::join!(
+            wait_for_in(&conn, &namespace, None),
+            async {
+                crate::runtime::sleep(Duration::from_millis(50)).await;
+                player.request_name(name.as_str()).await
+            },
+        );
+        requested?;
+        assert_eq!(found?.as_str(), name);
+        // Already owned
+        assert_eq!(wait_for(&conn, Some(&name)).await?.as_str(), name);
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn get_playing() -> anyhow::Result<()> {
+        let namespace = namespace("playing");
         let conn = zbus::Connection::session().await?;
 
-        let result: anyhow::Result<MediaPlayer2Proxy> = crate::sync::discovery::currently_playing(&conn).await;
+        let result = currently_playing_in::<PlayerProxy>(&conn, &namespace).await;
+        assert!(result.is_err_and(|e| e.to_string().starts_with("No currently active player found")));
 
-        match result {
-            Err(e) => {
-                assert!(e.to_string().starts_with("No currently active player found"))
-            }
-            Ok(proxy) => {
-                info!("identity={:?}, destination={:?}", proxy.identity().await?, proxy.inner().destination());
-            }
-        }
+        let _stopped = serve_named_player(&format!("{namespace}stopped"), false).await?;
+        let _playing = serve_named_player(&format!("{namespace}playing"), true).await?;
+        let proxy = currently_playing_in::<PlayerProxy>(&conn, &namespace).await?;
+        assert_eq!(proxy.inner().destination().as_str(), format!("{namespace}playing"));
 
         Ok(())
     }
-}
\ No newline at end of file
+}
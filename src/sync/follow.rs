@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::time::Duration;
+use futures::future::{self, Either};
+use futures::stream::{self, Stream, StreamExt};
+use zvariant::OwnedValue;
+use crate::runtime;
+use crate::shared::{Metadata, PlaybackStatus, TimeInUs, Volume};
+use crate::sync::PlayerProxy;
+
+/// What is shown of a followed player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStatus {
+    /// Bus name of the player.
+    pub player: String,
+    pub playback_status: PlaybackStatus,
+    pub metadata: Metadata,
+    pub volume: Volume,
+    /// Only read when following with a tick, see [`Follow::new`].
+    pub position: Option<TimeInUs>,
+}
+
+/// An event of a followed player, see [`Follow::next_event`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FollowEvent {
+    Changed(Box<PlayerStatus>),
+    /// The player left the bus.
+    Vanished,
+}
+
+enum Change {
+    Property,
+    Owner(bool),
+}
+
+/// Follows the playback status, track and volume of a player.
+///
+/// Built on the `receive_*_changed` streams, so the player has to be
+/// addressed by its well-known name to notice it leaving the bus. With a
+/// tick, the position is read periodically while playing.
+pub struct Follow {
+    proxy: PlayerProxy<'static>,
+    tick: Option<Duration>,
+    last: Option<PlayerStatus>,
+    changes: Pin<Box<dyn Stream<Item = Change> + Send>>,
+}
+
+impl Follow {
+    pub async fn new(proxy: &PlayerProxy<'_>, tick: Option<Duration>) -> zbus::Result<Self> {
+        let inner = proxy.inner();
+        // Cached, which the property streams need
+        let proxy = PlayerProxy::builder(inner.connection())
+            .destination(inner.destination().to_owned())?
+            .path(inner.path().to_owned())?
+            .build().await?;
+
+        let changes = stream::select_all([
+            proxy.receive_playback_status_changed().await.map(|_| Change::Property).boxed(),
+            // The generated stream borrows the proxy for its `Value<'_>`s
+            proxy.inner().receive_property_changed::<HashMap<String, OwnedValue>>("Metadata").await.map(|_| Change::Property).boxed(),
+            proxy.receive_volume_changed().await.map(|_| Change::Property).boxed(),
+            proxy.inner().receive_owner_changed().await?.map(|owner| Change::Owner(owner.is_some())).boxed(),
+        ]);
+
+        Ok(Self {
+            proxy,
+            tick,
+            last: None,
+            changes: Box::pin(changes),
+        })
+    }
+
+    pub fn proxy(&self) -> &PlayerProxy<'static> {
+        &self.proxy
+    }
+
+    /// Reads the current status of the player.
+    pub async fn status(&self) -> zbus::Result<PlayerStatus> {
+        let playback_status = self.proxy.playback_status().await?;
+        let position = match self.tick {
+            // Not every player can tell the position, e.g. while stopped
+            Some(_) => self.proxy.position().await.ok(),
+            None => None,
+        };
+
+        Ok(PlayerStatus {
+            player: self.proxy.inner().destination().to_string(),
+            playback_status,
+            metadata: Metadata::from(&self.proxy.metadata().await?),
+            volume: self.proxy.volume().await.unwrap_or(1.0),
+            position,
+        })
+    }
+
+    /// Waits for the status to change, the first call returns the current status.
+    ///
+    /// Returns [`None`] when the streams end, e.g. the connection is closed.
+    pub async fn next_event(&mut self) -> Option<zbus::Result<FollowEvent>> {
+        loop {
+            if self.last.is_some() {
+                let playing = self.last.as_ref().is_some_and(|it| it.playback_status == PlaybackStatus::Playing);
+                let tick = match self.tick {
+                    Some(tick) if playing => Either::Left(Box::pin(runtime::sleep(tick))),
+                    _ => Either::Right(future::pending()),
+                };
+                match future::select(self.changes.next(), tick).await {
+                    Either::Left((Some(Change::Owner(false)), _)) => {
+                        self.last = None;
+                        return Some(Ok(FollowEvent::Vanished));
+                    }
+                    Either::Left((Some(_), _)) | Either::Right(_) => {}
+                    Either::Left((None, _)) => return None,
+                }
+            }
+
+            let status = match self.status().await {
+                Ok(status) => status,
+                // Left the bus, the owner change follows
+                Err(_) if self.last.is_some() => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.last.as_ref() != Some(&status) {
+                self.last = Some(status.clone());
+                return Some(Ok(FollowEvent::Changed(Box::new(status))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use test_log::test;
+    use zbus::proxy::CacheProperties;
+    use zbus::Connection;
+    use zvariant::{ObjectPath, OwnedValue, Value};
+    use crate::server::engine::{FakeClock, PlaybackEngine};
+    use crate::server::player::PlayerServer;
+    use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+    use crate::shared::{PlaybackStatus, OBJECT_PATH};
+    use crate::sync::follow::{Follow, FollowEvent};
+    use crate::sync::PlayerProxy;
+
+    const NAME: &str = "org.mpris.MediaPlayer2.zmpris_test.follow";
+
+    #[test(tokio::test)]
+    async fn follow_changes() -> anyhow::Result<()> {
+        let server = Connection::session().await?;
+        let mut engine = PlaybackEngine::new(FakeClock::default());
+        let track = HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(ObjectPath::try_from("/track/1")?).try_to_owned()?),
+            ("xesam:title".to_string(), OwnedValue::from(zvariant::Str::from("First"))),
+        ]);
+        engine.set_tracks(vec![track], Some(0));
+        let mut player_server = PlayerServer::new(engine, StateStore::new(&server, MediaPlayer2State::default(), PlayerState::default())?);
+        player_server.update_backend(|_| Vec::new()).await?;
+        server.object_server().at(OBJECT_PATH, player_server).await?;
+        server.request_name(NAME).await?;
+
+        let conn = Connection::session().await?;
+        let proxy = PlayerProxy::builder(&conn)
+            .destination(NAME)?
+            .cache_properties(CacheProperties::No)
+            .build().await?;
+        let mut follow = Follow::new(&proxy, None).await?;
+
+        let Some(Ok(FollowEvent::Changed(status))) = follow.next_event().await else {
+            anyhow::bail!("Expected the initial status");
+        };
+        assert_eq!(status.playback_status, PlaybackStatus::Stopped);
+        assert_eq!(status.metadata.title.as_deref(), Some("First"));
+
+        proxy.play().await?;
+        let Some(Ok(FollowEvent::Changed(status))) = follow.next_event().await else {
+            anyhow::bail!("Expected a change");
+        };
+        assert_eq!(status.playback_status, PlaybackStatus::Playing);
+
+        proxy.set_volume(0.25).await?;
+        let Some(Ok(FollowEvent::Changed(status))) = follow.next_event().await else {
+            anyhow::bail!("Expected a change");
+        };
+        assert_eq!(status.volume, 0.25);
+
+        server.release_name(NAME).await?;
+        assert_eq!(follow.next_event().await.transpose()?, Some(FollowEvent::Vanished));
+
+        Ok(())
+    }
+}
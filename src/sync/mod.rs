@@ -1,4 +1,20 @@
 pub use crate::media_player::MediaPlayer2Proxy;
 pub use crate::player::PlayerProxy;
+pub use crate::playlists::PlaylistsProxy;
+pub use crate::track_list::TrackListProxy;
 
-pub mod discovery;
\ No newline at end of file
+// Returned by the `receive_seeked` methods
+pub use crate::player::{Seeked, SeekedArgs, SeekedStream};
+
+pub mod discovery;
+pub mod follow;
+pub mod playlist_cache;
+pub mod playlist_pages;
+pub mod playlist_search;
+pub mod queue;
+pub mod resilient;
+pub mod snapshot;
+pub mod track_list_mirror;
+pub mod tracklist_file;
+pub mod tracks_metadata;
+pub mod watcher;
\ No newline at end of file
@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::time::Duration;
+use futures::future::{self, Either};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use zbus::export::ordered_stream::{join, OrderedStreamExt};
+use zbus::fdo::PropertiesChanged;
+use zbus::proxy::CacheProperties;
+use zbus::Message;
+use crate::runtime;
+use crate::playlists::PlaylistChanged;
+use crate::shared::{Playlist, PlaylistOrdering, PLAYLISTS_INTERFACE};
+use crate::sync::discovery::properties;
+use crate::sync::playlist_pages::playlists;
+use crate::sync::PlaylistsProxy;
+
+/// A change applied to a [`PlaylistCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistChange {
+    Added(Playlist),
+    Removed(Playlist),
+    /// The playlist with the same id got a new name.
+    Renamed { previous: Playlist, playlist: Playlist },
+    /// The playlist with the same id got a new icon.
+    IconChanged { previous: Playlist, playlist: Playlist },
+}
+
+/// A local copy of a player's playlists, in the player's alphabetical order.
+///
+/// The cache is updated from the `PlaylistChanged` signal and refetched
+/// when `PlaylistCount` or `Orderings` change, or when a playlist is renamed,
+/// which may move it. As players may not implement the signal, the cache
+/// can also be refetched periodically.
+///
+/// Signals are applied while reading changes with
+/// [`next_change`](Self::next_change) or [`changes`](Self::changes).
+pub struct PlaylistCache {
+    proxy: PlaylistsProxy<'static>,
+    playlists: Vec<Playlist>,
+    pending: VecDeque<PlaylistChange>,
+    poll_interval: Option<Duration>,
+    signals: Pin<Box<dyn Stream<Item = Message> + Send>>,
+}
+
+impl PlaylistCache {
+    /// Creates a cache of the playlists of `proxy`'s player.
+    ///
+    /// With a `poll_interval`, the playlists are also refetched whenever no
+    /// signal arrived for that long.
+    pub async fn new(proxy: &PlaylistsProxy<'_>, poll_interval: Option<Duration>) -> zbus::Result<Self> {
+        let inner = proxy.inner();
+        // Uncached, so a refetch never sees a count older than the signal that triggered it
+        let proxy = PlaylistsProxy::builder(inner.connection())
+            .destination(inner.destination().to_owned())?
+            .path(inner.path().to_owned())?
+            .cache_properties(CacheProperties::No)
+            .build().await?;
+        let properties = properties(inner).await?;
+
+        let signals = join(
+            proxy.receive_playlist_changed().await?.into_inner(),
+            properties.receive_properties_changed_with_args(&[(0, PLAYLISTS_INTERFACE)]).await?.into_inner(),
+        ).into_stream();
+
+        let mut cache = Self {
+            proxy,
+            playlists: Vec::new(),
+            pending: VecDeque::new(),
+            poll_interval,
+            signals: Box::pin(signals),
+        };
+        cache.refresh().await?;
+        // The initial fetch is not a change
+        cache.pending.clear();
+
+        Ok(cache)
+    }
+
+    /// The cached playlists.
+    pub fn playlists(&self) -> &[Playlist] {
+        &self.playlists
+    }
+
+    /// Waits for the next change of the playlists and applies it to the cache.
+    ///
+    /// Returns [`None`] when the signal stream ends, e.g. the connection is closed.
+    pub async fn next_change(&mut self) -> Option<zbus::Result<PlaylistChange>> {
+        loop {
+            if let Some(change) = self.pending.pop_front() {
+                return Some(Ok(change));
+            }
+
+            let poll = match self.poll_interval {
+                Some(interval) => Either::Left(Box::pin(runtime::sleep(interval))),
+                None => Either::Right(future::pending()),
+            };
+            let result = match future::select(self.signals.next(), poll).await {
+                Either::Left((Some(message), _)) => self.apply(message).await,
+                Either::Left((None, _)) => return None,
+                Either::Right(_) => self.refresh().await,
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Stream of the changes of the playlists, see [`next_change`](Self::next_change).
+    pub fn changes(&mut self) -> impl Stream<Item = zbus::Result<PlaylistChange>> + Unpin + '_ {
+        Box::pin(stream::unfold(self, |cache| async move {
+            let change = cache.next_change().await?;
+            Some((change, cache))
+        }))
+    }
+
+    /// Refetches all playlists and queues the differences to the cache.
+    ///
+    /// The queued changes are returned by [`next_change`](Self::next_change).
+    pub async fn refresh(&mut self) -> zbus::Result<()> {
+        let fetched: Vec<Playlist> = playlists(&self.proxy, PlaylistOrdering::Alphabetical, false).try_collect().await?;
+
+        for previous in &self.playlists {
+            match fetched.iter().find(|it| it.id() == previous.id()) {
+                Some(playlist) => self.pending.extend(diff(previous, playlist)),
+                None => self.pending.push_back(PlaylistChange::Removed(previous.clone())),
+            }
+        }
+        for playlist in &fetched {
+            if !self.playlists.iter().any(|it| it.id() == playlist.id()) {
+                self.pending.push_back(PlaylistChange::Added(playlist.clone()));
+            }
+        }
+
+        self.playlists = fetched;
+        Ok(())
+    }
+
+    async fn apply(&mut self, message: Message) -> zbus::Result<()> {
+        if let Some(signal) = PlaylistChanged::from_message(message.clone()) {
+            let playlist = signal.args()?.playlist;
+            return match self.playlists.iter_mut().find(|it| it.id() == playlist.id()) {
+                Some(previous) if previous.name() == playlist.name() => {
+                    self.pending.extend(diff(previous, &playlist));
+                    *previous = playlist;
+                    Ok(())
+                }
+                _ => self.refresh().await,
+            };
+        }
+
+        if let Some(signal) = PropertiesChanged::from_message(message) {
+            let args = signal.args()?;
+            let changed = |property: &str| args.changed_properties().contains_key(property)
+                || args.invalidated_properties().contains(&property);
+            if changed("PlaylistCount") || changed("Orderings") {
+                return self.refresh().await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn diff(previous: &Playlist, playlist: &Playlist) -> Vec<PlaylistChange> {
+    let mut changes = Vec::new();
+    if previous.name() != playlist.name() {
+        changes.push(PlaylistChange::Renamed { previous: previous.clone(), playlist: playlist.clone() });
+    }
+    if previous.uri() != playlist.uri() {
+        changes.push(PlaylistChange::IconChanged { previous: previous.clone(), playlist: playlist.clone() });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zbus::object_server::SignalEmitter;
+    use zbus::Connection;
+    use crate::runtime;
+    use crate::server::playlists::PlaylistsServer;
+    use crate::shared::{Playlist, OBJECT_PATH};
+    use crate::sync::playlist_cache::{PlaylistCache, PlaylistChange};
+    use crate::test_util::{playlist_dir, proxy, serve};
+
+    #[test(tokio::test)]
+    async fn cache_changes() -> anyhow::Result<()> {
+        let directory = playlist_dir("cache", &[("chill.m3u", "#PLAYLIST:Chill\n"), ("party.m3u", "#PLAYLIST:Party\n")])?;
+        let (server, iface) = serve(PlaylistsServer::new(&directory)?).await?;
+        let emitter = iface.signal_emitter();
+        let proxy = proxy(&server).await?;
+        let served = || async { iface.get().await.playlist_list() };
+        let [chill, party]: [Playlist; 2] = served().await.try_into().expect("Two playlists");
+
+        let mut cache = PlaylistCache::new(&proxy, None).await?;
+        assert_eq!(cache.playlists(), [chill.clone(), party.clone()]);
+
+        std::fs::write(directory.join("chill.m3u"), "#PLAYLIST:Relax\n")?;
+        iface.get_mut().await.rescan(emitter).await?;
+        let relax = served().await[0].clone();
+        assert_eq!(cache.next_change().await.transpose()?, Some(PlaylistChange::Renamed { previous: chill, playlist: relax.clone() }));
+        assert_eq!(cache.playlists(), [party.clone(), relax.clone()]);
+
+        std::fs::write(directory.join("focus.m3u"), "#PLAYLIST:Focus\n")?;
+        iface.get_mut().await.rescan(emitter).await?;
+        let focus = served().await[1].clone();
+        assert_eq!(cache.changes().next().await.transpose()?, Some(PlaylistChange::Added(focus.clone())));
+
+        // Announced by another connection, so only refetching notices
+        let mut polling = PlaylistCache::new(&proxy, Some(Duration::ZERO)).await?;
+        std::fs::remove_file(directory.join("party.m3u"))?;
+        let other = Connection::session().await?;
+        iface.get_mut().await.rescan(&SignalEmitter::new(&other, OBJECT_PATH)?).await?;
+        let change = runtime::timeout(Duration::from_secs(5), polling.next_change()).await.expect("Not refetched");
+        assert_eq!(change.transpose()?, Some(PlaylistChange::Removed(party.clone())));
+
+        cache.refresh().await?;
+        assert_eq!(cache.next_change().await.transpose()?, Some(PlaylistChange::Removed(party)));
+        assert_eq!(cache.playlists(), [focus, relax]);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use futures::stream::{self, Stream, TryStreamExt};
+use crate::shared::{Playlist, PlaylistOrdering};
+use crate::sync::PlaylistsProxy;
+
+/// Number of playlists fetched per `GetPlaylists` call by [`playlists`].
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// Returns `requested` if the player supports it, otherwise the first
+/// ordering it offers.
+pub async fn resolve_ordering(proxy: &PlaylistsProxy<'_>, requested: PlaylistOrdering) -> zbus::Result<PlaylistOrdering> {
+    let orderings = proxy.orderings().await?.0;
+    if orderings.contains(&requested) {
+        return Ok(requested);
+    }
+    // At least one ordering must be offered, keep the requested one if the player breaks that
+    Ok(orderings.first().copied().unwrap_or(requested))
+}
+
+/// Streams every playlist of the player, fetching them in pages of [`DEFAULT_PAGE_SIZE`].
+///
+/// See [`playlists_paged`].
+pub fn playlists<'a>(
+    proxy: &'a PlaylistsProxy<'_>,
+    order: PlaylistOrdering,
+    reverse_order: bool,
+) -> impl Stream<Item = zbus::Result<Playlist>> + 'a {
+    playlists_paged(proxy, order, reverse_order, DEFAULT_PAGE_SIZE)
+}
+
+/// Streams every playlist of the player, fetching `page_size` playlists per
+/// `GetPlaylists` call.
+///
+/// If `order` is not in `Orderings`, an ordering the player supports is used
+/// instead, see [`resolve_ordering`]. Paging stops after `PlaylistCount`
+/// playlists or at the first empty page, whichever comes first.
+pub fn playlists_paged<'a>(
+    proxy: &'a PlaylistsProxy<'_>,
+    order: PlaylistOrdering,
+    reverse_order: bool,
+    page_size: u32,
+) -> impl Stream<Item = zbus::Result<Playlist>> + 'a {
+    let page_size = page_size.max(1);
+    stream::try_unfold(None, move |state: Option<(u32, u32, PlaylistOrdering)>| async move {
+        let (index, count, order) = match state {
+            Some(state) => state,
+            None => (0, proxy.playlist_count().await?, resolve_ordering(proxy, order).await?),
+        };
+        if index >= count {
+            return zbus::Result::Ok(None);
+        }
+
+        let page = proxy.get_playlists(index, page_size.min(count - index), order, reverse_order).await?;
+        if page.is_empty() {
+            return Ok(None);
+        }
+        let next = index.saturating_add(page.len() as u32);
+        Ok(Some((page, Some((next, count, order)))))
+    })
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream::TryStreamExt;
+    use test_log::test;
+    use crate::server::playlists::{PlaylistsServer, USER_ORDER_FILE};
+    use crate::shared::{Playlist, PlaylistOrdering};
+    use crate::sync::playlist_pages::{playlists_paged, resolve_ordering};
+    use crate::test_util::{playlist_dir, proxy, serve};
+
+    #[test(tokio::test)]
+    async fn page_through_playlists() -> anyhow::Result<()> {
+        let directory = playlist_dir("pages", &[
+            ("d.m3u", ""), ("b.m3u", ""), ("e.m3u", ""), ("a.m3u", ""), ("c.m3u", ""),
+            (USER_ORDER_FILE, "d.m3u\nb.m3u\ne.m3u\na.m3u\nc.m3u\n"),
+        ])?;
+        let playlists = PlaylistsServer::new(&directory)?
+            .with_orderings([PlaylistOrdering::Alphabetical, PlaylistOrdering::User]);
+        let (server, _) = serve(playlists).await?;
+        let proxy = proxy(&server).await?;
+
+        assert_eq!(resolve_ordering(&proxy, PlaylistOrdering::User).await?, PlaylistOrdering::User);
+        assert_eq!(resolve_ordering(&proxy, PlaylistOrdering::Created).await?, PlaylistOrdering::Alphabetical);
+
+        let names = |playlists: Vec<Playlist>| playlists.iter().map(|it| it.name().to_string()).collect::<Vec<_>>();
+        let all: Vec<Playlist> = playlists_paged(&proxy, PlaylistOrdering::Created, false, 2).try_collect().await?;
+        assert_eq!(names(all), ["a", "b", "c", "d", "e"]);
+
+        let all: Vec<Playlist> = playlists_paged(&proxy, PlaylistOrdering::User, true, 2).try_collect().await?;
+        assert_eq!(names(all), ["c", "a", "e", "b", "d"]);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
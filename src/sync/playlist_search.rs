@@ -0,0 +1,232 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use anyhow::Result;
+use futures::stream::{StreamExt, TryStreamExt};
+use zbus::fdo::PropertiesProxy;
+use zbus::names::InterfaceName;
+use crate::runtime;
+use crate::shared::{Playlist, PlaylistOrdering, W, PLAYLISTS_INTERFACE};
+use crate::sync::discovery::properties;
+use crate::sync::playlist_pages::playlists;
+use crate::sync::PlaylistsProxy;
+
+/// Why [`find_playlist_by_name`] couldn't pick a single playlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistLookupError {
+    /// No playlist name matches, not even fuzzily.
+    NotFound(String),
+    /// Several playlists match equally well.
+    Ambiguous { name: String, candidates: Vec<Playlist> },
+}
+
+impl Display for PlaylistLookupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "No playlist matches \"{name}\""),
+            Self::Ambiguous { name, candidates } => {
+                let names: Vec<_> = candidates.iter().map(|it| format!("\"{}\"", it.name())).collect();
+                write!(f, "\"{name}\" matches several playlists: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlaylistLookupError {}
+
+/// Result of [`activate_playlist_by_name`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    /// The activated playlist.
+    pub playlist: Playlist,
+    /// Whether the player confirmed the activation in time by making the
+    /// playlist its `ActivePlaylist`.
+    pub confirmed: bool,
+}
+
+/// Finds the playlist called `name`.
+///
+/// Exact matches are preferred, then case-insensitive ones, then the best
+/// fuzzy match. Fails with a [`PlaylistLookupError`] if nothing matches or
+/// several playlists match equally well.
+pub async fn find_playlist_by_name(proxy: &PlaylistsProxy<'_>, name: &str) -> Result<Playlist> {
+    let all: Vec<Playlist> = playlists(proxy, PlaylistOrdering::Alphabetical, false).try_collect().await?;
+    Ok(pick(all, name)?)
+}
+
+/// Finds the playlist called `name` as [`find_playlist_by_name`] does and activates it.
+///
+/// Waits up to `timeout` for the player to confirm the activation. As
+/// players may just insert the playlist into the tracklist, an unconfirmed
+/// activation is not an error, see [`Activation::confirmed`].
+pub async fn activate_playlist_by_name(proxy: &PlaylistsProxy<'_>, name: &str, timeout: Duration) -> Result<Activation> {
+    let playlist = find_playlist_by_name(proxy, name).await?;
+
+    let inner = proxy.inner();
+    let properties = properties(inner).await?;
+    // Subscribed before activating, so the confirmation can't be missed
+    let mut changes = properties.receive_properties_changed().await?;
+
+    proxy.activate_playlist(playlist.id()).await?;
+
+    let confirmation = async {
+        if is_active(&properties, &playlist).await? {
+            return zbus::Result::Ok(true);
+        }
+        while let Some(change) = changes.next().await {
+            let args = change.args()?;
+            // A changed track may come from anywhere, only the active playlist tells which one was activated
+            let changed = args.interface_name().as_str() == PLAYLISTS_INTERFACE
+                && (args.changed_properties().contains_key("ActivePlaylist")
+                    || args.invalidated_properties().contains(&"ActivePlaylist"));
+            if changed && is_active(&properties, &playlist).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    let confirmed = runtime::timeout(timeout, confirmation).await.unwrap_or(Ok(false))?;
+
+    Ok(Activation { playlist, confirmed })
+}
+
+async fn is_active(properties: &PropertiesProxy<'_>, playlist: &Playlist) -> zbus::Result<bool> {
+    let interface = InterfaceName::from_static_str_unchecked(PLAYLISTS_INTERFACE);
+    let active = W::<Option<Playlist>>::try_from(properties.get(interface, "ActivePlaylist").await?)?;
+    Ok(active.0.is_some_and(|it| it.id() == playlist.id()))
+}
+
+fn pick(all: Vec<Playlist>, name: &str) -> Result<Playlist, PlaylistLookupError> {
+    let exact: Vec<&Playlist> = all.iter().filter(|it| it.name() == name).collect();
+    let matches = if !exact.is_empty() {
+        exact
+    } else {
+        let insensitive: Vec<&Playlist> = all.iter()
+            .filter(|it| it.name().to_lowercase() == name.to_lowercase())
+            .collect();
+        if !insensitive.is_empty() {
+            insensitive
+        } else {
+            let scored: Vec<(u32, &Playlist)> = all.iter()
+                .filter_map(|it| Some((fuzzy_score(name, it.name())?, it)))
+                .collect();
+            let best = scored.iter().map(|(score, _)| *score).max();
+            scored.into_iter()
+                .filter(|(score, _)| Some(*score) == best)
+                .map(|(_, it)| it)
+                .collect()
+        }
+    };
+
+    match matches.as_slice() {
+        [] => Err(PlaylistLookupError::NotFound(name.to_string())),
+        [playlist] => Ok((*playlist).clone()),
+        _ => Err(PlaylistLookupError::Ambiguous {
+            name: name.to_string(),
+            candidates: matches.into_iter().cloned().collect(),
+        }),
+    }
+}
+
+/// Scores how well `query` matches `candidate`, ignoring case and whitespace.
+///
+/// The characters of `query` must appear in `candidate` in order.
+/// Consecutive characters and characters starting a word score higher,
+/// a substring match scores higher than any scattered match.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let query: Vec<char> = normalize(query).into_iter().map(|(it, _)| it).collect();
+    let (candidate, word_starts): (Vec<char>, Vec<bool>) = normalize(candidate).into_iter().unzip();
+    if query.is_empty() {
+        return None;
+    }
+
+    let mut score: u32 = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in &query {
+        let found = position + candidate[position..].iter().position(|it| it == wanted)?;
+        score += 1;
+        if previous.is_some_and(|it| it + 1 == found) {
+            score += 4;
+        }
+        if word_starts[found] {
+            score += 2;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+
+    let substring = candidate.windows(query.len()).any(|it| it == query.as_slice());
+    let bonus = if substring { 1_000 } else { 0 };
+    // Among equal matches, prefer the shortest candidate
+    Some(bonus + (score * 100).saturating_sub(candidate.len() as u32))
+}
+
+/// Lowercase characters of `value` without whitespace, each with whether it starts a word.
+fn normalize(value: &str) -> Vec<(char, bool)> {
+    let mut normalized = Vec::with_capacity(value.len());
+    let mut word_start = true;
+    for char in value.chars().flat_map(char::to_lowercase) {
+        if char.is_alphanumeric() {
+            normalized.push((char, word_start));
+            word_start = false;
+        } else {
+            if !char.is_whitespace() {
+                normalized.push((char, word_start));
+            }
+            word_start = true;
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use test_log::test;
+    use zvariant::ObjectPath;
+    use crate::server::playlists::PlaylistsServer;
+    use crate::shared::Playlist;
+    use crate::sync::playlist_search::{activate_playlist_by_name, pick, PlaylistLookupError};
+    use crate::test_util::{playlist_dir, proxy, serve};
+
+    fn playlist(name: &str) -> Playlist {
+        let id = ObjectPath::try_from(format!("/playlist/{}", name.replace(' ', "_"))).unwrap().into();
+        Playlist::new(id, name.to_string(), String::new())
+    }
+
+    fn names(names: &[&str]) -> Vec<Playlist> {
+        names.iter().map(|it| playlist(it)).collect()
+    }
+
+    #[test(tokio::test)]
+    async fn pick_by_name() -> anyhow::Result<()> {
+        let all = names(&["Rock", "rock", "Road Trip", "Morning Coffee", "Workout", "Work Focus"]);
+
+        assert_eq!(pick(all.clone(), "rock")?.name(), "rock");
+        assert_eq!(pick(all.clone(), "morning coffee")?.name(), "Morning Coffee");
+        assert_eq!(pick(all.clone(), "coffee")?.name(), "Morning Coffee");
+        assert_eq!(pick(all.clone(), "rdtrp")?.name(), "Road Trip");
+        assert_eq!(pick(names(&["Road Trip", "Roadtrip Mix"]), "roadtrip")?.name(), "Road Trip");
+        assert_eq!(pick(all.clone(), "jazz"), Err(PlaylistLookupError::NotFound("jazz".to_string())));
+        assert_eq!(
+            pick(all.clone(), "ROCK"),
+            Err(PlaylistLookupError::Ambiguous { name: "ROCK".to_string(), candidates: names(&["Rock", "rock"]) }),
+        );
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn activate_by_name() -> anyhow::Result<()> {
+        let directory = playlist_dir("search", &[("Chill.m3u", ""), ("Party.m3u", "")])?;
+        let (server, iface) = serve(PlaylistsServer::new(&directory)?).await?;
+
+        let activation = activate_playlist_by_name(&proxy(&server).await?, "party", Duration::from_secs(5)).await?;
+        assert!(activation.confirmed);
+        assert_eq!(activation.playlist.name(), "Party");
+        assert_eq!(iface.get().await.active().map(|it| it.name().to_string()), Some("Party".to_string()));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use anyhow::{anyhow, bail, Result};
+use zvariant::{ObjectPath, OwnedObjectPath};
+use crate::shared::{Metadata, TrackId, TrackIdExt, NO_TRACK};
+use crate::sync::tracks_metadata::{get_tracks, get_tracks_metadata};
+use crate::sync::{PlayerProxy, TrackListProxy};
+
+/// Appends `uris` to the end of the tracklist, in order.
+pub async fn enqueue(track_list: &TrackListProxy<'_>, uris: &[&str]) -> Result<()> {
+    ensure_editable(track_list).await?;
+    let tracks = get_tracks(track_list).await?;
+    let after = match tracks.last() {
+        Some(last) => last.as_ref(),
+        None => ObjectPath::from_static_str_unchecked(NO_TRACK),
+    };
+    add_after(track_list, uris, &after).await
+}
+
+/// Inserts `uris` after the track `after`, in order.
+///
+/// Pass [`NO_TRACK`] to insert them at the start of the tracklist.
+pub async fn insert_after(track_list: &TrackListProxy<'_>, uris: &[&str], after: &TrackId<'_>) -> Result<()> {
+    ensure_editable(track_list).await?;
+    add_after(track_list, uris, after).await
+}
+
+/// Inserts `uris` right after the current track, so they play next.
+///
+/// If there is no current track, they are inserted at the start of the tracklist.
+pub async fn play_next(track_list: &TrackListProxy<'_>, player: &PlayerProxy<'_>, uris: &[&str]) -> Result<()> {
+    ensure_editable(track_list).await?;
+    let after = current_track(player).await?
+        .unwrap_or_else(|| ObjectPath::from_static_str_unchecked(NO_TRACK).into());
+    add_after(track_list, uris, &after).await
+}
+
+/// Moves `track_id` after the track `after` by removing it and adding its
+/// `xesam:url` again.
+///
+/// Note that the moved track gets a new id. Removing the current track would
+/// interrupt the playback, so moving it fails, move the other tracks around it
+/// instead.
+pub async fn move_track(
+    track_list: &TrackListProxy<'_>,
+    player: &PlayerProxy<'_>,
+    track_id: &TrackId<'_>,
+    after: &TrackId<'_>,
+) -> Result<()> {
+    ensure_editable(track_list).await?;
+    if track_id == after {
+        return Ok(());
+    }
+    if current_track(player).await?.as_deref() == Some(track_id) {
+        bail!("The current track can't be moved without interrupting the playback.");
+    }
+
+    let id = OwnedObjectPath::from(track_id.to_owned());
+    let url = get_tracks_metadata(track_list, std::slice::from_ref(&id)).await?
+        .into_iter()
+        .find_map(|(_, metadata)| metadata.url)
+        .ok_or(anyhow!("Track {track_id} has no xesam:url to re-add it from"))?;
+
+    track_list.remove_track(track_id).await?;
+    track_list.add_track(&url, after, false).await?;
+    Ok(())
+}
+
+/// Removes every track but the current one.
+pub async fn clear_except_current(track_list: &TrackListProxy<'_>, player: &PlayerProxy<'_>) -> Result<()> {
+    ensure_editable(track_list).await?;
+    let current = current_track(player).await?;
+    for id in get_tracks(track_list).await? {
+        if Some(&id) != current.as_ref() {
+            track_list.remove_track(&id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes tracks whose `xesam:url` already appeared earlier in the tracklist.
+///
+/// The current track is never removed, its duplicates are removed instead.
+///
+/// ## Returns
+/// The number of removed tracks.
+pub async fn remove_duplicates(track_list: &TrackListProxy<'_>, player: &PlayerProxy<'_>) -> Result<usize> {
+    ensure_editable(track_list).await?;
+    let current = current_track(player).await?;
+    let tracks = get_tracks_metadata(track_list, &get_tracks(track_list).await?).await?;
+
+    let mut seen: HashSet<String> = tracks.iter()
+        .filter(|(id, _)| Some(id) == current.as_ref())
+        .filter_map(|(_, metadata)| metadata.url.clone())
+        .collect();
+    let mut removed = 0;
+    for (id, metadata) in tracks {
+        if Some(&id) == current.as_ref() {
+            continue;
+        }
+        let Some(url) = metadata.url else {
+            continue;
+        };
+        if !seen.insert(url) {
+            track_list.remove_track(&id).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+async fn ensure_editable(track_list: &TrackListProxy<'_>) -> Result<()> {
+    if !track_list.can_edit_tracks().await? {
+        bail!("The player does not allow editing its tracklist.");
+    }
+    Ok(())
+}
+
+async fn add_after(track_list: &TrackListProxy<'_>, uris: &[&str], after: &TrackId<'_>) -> Result<()> {
+    // `AddTrack` doesn't return the new id, so adding in reverse after the
+    // same track is the only way to keep the given order
+    for uri in uris.iter().rev() {
+        track_list.add_track(uri, after, false).await?;
+    }
+    Ok(())
+}
+
+async fn current_track(player: &PlayerProxy<'_>) -> zbus::Result<Option<OwnedObjectPath>> {
+    let metadata = player.metadata().await?;
+    Ok(Metadata::from(&metadata).track_id.filter(|it| !it.is_no_track()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use test_log::test;
+    use zbus::object_server::InterfaceRef;
+    use zvariant::Value;
+    use crate::server::state::MediaPlayer2State;
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::shared::{Metadata, OBJECT_PATH};
+    use crate::sync::queue::*;
+    use crate::test_util::{proxy, serve_player};
+
+    async fn uris(track_list: &InterfaceRef<TrackListServer>) -> Vec<String> {
+        track_list.get().await.track_list().iter()
+            .filter_map(|it| Metadata::from(&it.metadata).url)
+            .collect()
+    }
+
+    #[test(tokio::test)]
+    async fn queue_operations() -> anyhow::Result<()> {
+        let (server, player) = serve_player(MediaPlayer2State { has_track_list: true, ..Default::default() }).await?;
+        server.object_server().at(OBJECT_PATH, TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let tracks = server.object_server().interface::<_, TrackListServer>(OBJECT_PATH).await?;
+        let track_list = proxy(&server).await?;
+        let player_proxy = proxy(&server).await?;
+
+        enqueue(&track_list, &["file:///a", "file:///b", "file:///c"]).await?;
+        assert_eq!(uris(&tracks).await, ["file:///a", "file:///b", "file:///c"]);
+
+        // The first track is the current one
+        let current = HashMap::from([
+            ("mpris:trackid".to_string(), Value::from(tracks.get().await.track_list()[0].id.clone()).try_to_owned()?),
+        ]);
+        player.get_mut().await.update_backend(|engine| engine.set_tracks(vec![current], Some(0))).await?;
+
+        play_next(&track_list, &player_proxy, &["file:///c", "file:///d"]).await?;
+        assert_eq!(uris(&tracks).await, ["file:///a", "file:///c", "file:///d", "file:///b", "file:///c"]);
+
+        let (first, last) = {
+            let tracks = tracks.get().await;
+            (tracks.track_list()[1].id.clone(), tracks.track_list()[4].id.clone())
+        };
+        move_track(&track_list, &player_proxy, &first, &last).await?;
+        assert_eq!(uris(&tracks).await, ["file:///a", "file:///d", "file:///b", "file:///c", "file:///c"]);
+
+        let current = tracks.get().await.track_list()[0].id.clone();
+        assert!(move_track(&track_list, &player_proxy, &current, &last).await.is_err());
+        assert_eq!(uris(&tracks).await, ["file:///a", "file:///d", "file:///b", "file:///c", "file:///c"]);
+
+        enqueue(&track_list, &["file:///a"]).await?;
+        assert_eq!(remove_duplicates(&track_list, &player_proxy).await?, 2);
+        assert_eq!(uris(&tracks).await, ["file:///a", "file:///d", "file:///b", "file:///c"]);
+
+        clear_except_current(&track_list, &player_proxy).await?;
+        assert_eq!(uris(&tracks).await, ["file:///a"]);
+
+        Ok(())
+    }
+}
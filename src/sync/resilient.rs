@@ -0,0 +1,303 @@
+use std::future::Future;
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use zbus::fdo::{DBusProxy, NameOwnerChangedStream};
+use zbus::names::{OwnedUniqueName, OwnedWellKnownName, WellKnownName};
+use zbus::Connection;
+use crate::shared::PlayerEvent;
+use crate::sync::watcher::{PlayerEvents, Watcher};
+use crate::sync::{PlayerProxy, Seeked};
+
+/// An event of a [`ResilientPlayer`].
+#[derive(Debug, PartialEq)]
+pub enum ResilientEvent {
+    /// The current owner of the name emitted a player event.
+    Player(PlayerEvent),
+    /// The name lost its owner, e.g. the player exited.
+    Disconnected,
+    /// The name got a new owner and the player has been rebound to it.
+    Reconnected(OwnedUniqueName),
+}
+
+/// A player bound to a well-known name rather than to the process owning it.
+///
+/// Proxies obtained from [`by_name`](crate::sync::discovery::by_name) keep
+/// their state for the owner they were created for, so they go stale when a
+/// player restarts. This wrapper follows the name: whenever it changes
+/// owner, the proxy is rebuilt, the events read with
+/// [`next_event`](Self::next_event) are moved to the new owner and
+/// [`ResilientEvent::Reconnected`] is emitted.
+///
+/// The player is rebound while events are read with
+/// [`next_event`](Self::next_event), so long-lived users should keep
+/// polling it. Streams of the proxy, like the `receive_*_changed` ones,
+/// are subscribed to every new owner when obtained through
+/// [`resubscribe`](Self::resubscribe).
+pub struct ResilientPlayer {
+    conn: Connection,
+    name: OwnedWellKnownName,
+    proxy: Option<PlayerProxy<'static>>,
+    watcher: Watcher,
+    events: Option<PlayerEvents>,
+    owner_changes: NameOwnerChangedStream,
+}
+
+impl ResilientPlayer {
+    /// Creates a player following `name`.
+    ///
+    /// The name doesn't need to have an owner yet, in this case
+    /// [`proxy`](Self::proxy) returns [`None`] until the player appears.
+    pub async fn new(conn: &Connection, name: &str) -> zbus::Result<Self> {
+        let name = OwnedWellKnownName::from(WellKnownName::try_from(name)?.into_owned());
+        let dbus = DBusProxy::new(conn).await?;
+        let owner_changes = dbus.receive_name_owner_changed_with_args(&[(0, name.as_str())]).await?;
+
+        let mut player = Self {
+            conn: conn.clone(),
+            name,
+            proxy: None,
+            watcher: Watcher::new(conn).await?,
+            events: None,
+            owner_changes,
+        };
+        if let Ok(owner) = dbus.get_name_owner((&player.name).into()).await {
+            player.bind(owner).await?;
+        }
+
+        Ok(player)
+    }
+
+    /// Well-known name this player follows.
+    pub fn name(&self) -> &WellKnownName<'static> {
+        &self.name
+    }
+
+    /// Proxy for the current owner of the name, if any.
+    pub fn proxy(&self) -> Option<&PlayerProxy<'static>> {
+        self.proxy.as_ref()
+    }
+
+    /// Returns the stream `subscribe` creates from the proxy of the current
+    /// owner, subscribed again to every new owner of the name.
+    ///
+    /// The stream pauses while the name has no owner and ends with the
+    /// connection. Fails if subscribing to the current owner fails.
+    pub async fn resubscribe<T, F, Fut, S>(&self, mut subscribe: F) -> zbus::Result<BoxStream<'static, T>>
+    where
+        F: FnMut(PlayerProxy<'static>) -> Fut + Send + 'static,
+        Fut: Future<Output = zbus::Result<S>> + Send + 'static,
+        S: Stream<Item = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let dbus = DBusProxy::new(&self.conn).await?;
+        let mut resubscription = Resubscription {
+            conn: self.conn.clone(),
+            name: self.name.clone(),
+            // Before the owner is read, so no change is missed
+            owner_changes: dbus.receive_name_owner_changed_with_args(&[(0, self.name.as_str())]).await?,
+            subscribe: Box::new(move |proxy| subscribe(proxy).map(|it| it.map(StreamExt::boxed)).boxed()),
+            stream: None,
+        };
+        if dbus.get_name_owner((&self.name).into()).await.is_ok() {
+            resubscription.stream = Some(resubscription.bind().await?);
+        }
+
+        Ok(stream::unfold(resubscription, |mut it| async move { it.next().await.map(|item| (item, it)) }).boxed())
+    }
+
+    /// Same as [`PlayerProxy::receive_seeked`], subscribed to every new owner.
+    pub async fn receive_seeked(&self) -> zbus::Result<BoxStream<'static, Seeked>> {
+        self.resubscribe(|proxy| async move { proxy.receive_seeked().await }).await
+    }
+
+    /// Waits for the next event, rebinding the player when the name
+    /// changes owner.
+    ///
+    /// Returns [`None`] only when the connection to the bus is closed.
+    pub async fn next_event(&mut self) -> Option<ResilientEvent> {
+        loop {
+            let player_event = match self.events.as_mut() {
+                Some(events) => Either::Left(events.next()),
+                None => Either::Right(future::pending()),
+            };
+
+            match future::select(self.owner_changes.next(), player_event).await {
+                Either::Left((change, _)) => {
+                    let change = change?;
+                    let Ok(args) = change.args() else {
+                        continue;
+                    };
+                    match args.new_owner().as_ref().map(|it| OwnedUniqueName::from(it.to_owned())) {
+                        Some(owner) => match self.bind(owner).await {
+                            Ok(owner) => return Some(ResilientEvent::Reconnected(owner)),
+                            Err(e) => {
+                                log::debug!("Failed to rebind {}: {e}", self.name);
+                                self.unbind();
+                            }
+                        },
+                        None => {
+                            self.unbind();
+                            return Some(ResilientEvent::Disconnected);
+                        }
+                    }
+                }
+                Either::Right((Some(event), _)) => return Some(ResilientEvent::Player(event)),
+                // The owner gave up its name, the matching `NameOwnerChanged` follows
+                Either::Right((None, _)) => self.events = None,
+            }
+        }
+    }
+
+    async fn bind(&mut self, owner: OwnedUniqueName) -> zbus::Result<OwnedUniqueName> {
+        let proxy = PlayerProxy::builder(&self.conn)
+            .destination(self.name.clone())?
+            .build().await?;
+        self.events = Some(self.watcher.subscribe(owner.clone()).await?);
+        self.proxy = Some(proxy);
+        Ok(owner)
+    }
+
+    fn unbind(&mut self) {
+        self.proxy = None;
+        self.events = None;
+    }
+}
+
+type Subscribe<T> = Box<dyn FnMut(PlayerProxy<'static>) -> BoxFuture<'static, zbus::Result<BoxStream<'static, T>>> + Send>;
+
+/// State of a stream of [`ResilientPlayer::resubscribe`].
+struct Resubscription<T> {
+    conn: Connection,
+    name: OwnedWellKnownName,
+    owner_changes: NameOwnerChangedStream,
+    subscribe: Subscribe<T>,
+    stream: Option<BoxStream<'static, T>>,
+}
+
+impl<T> Resubscription<T> {
+    async fn next(&mut self) -> Option<T> {
+        loop {
+            let item = match self.stream.as_mut() {
+                Some(stream) => Either::Left(stream.next()),
+                None => Either::Right(future::pending()),
+            };
+
+            match future::select(self.owner_changes.next(), item).await {
+                Either::Left((change, _)) => {
+                    let change = change?;
+                    let Ok(args) = change.args() else {
+                        continue;
+                    };
+                    let owned = args.new_owner().is_some();
+                    self.stream = None;
+                    if owned {
+                        match self.bind().await {
+                            Ok(stream) => self.stream = Some(stream),
+                            Err(e) => log::debug!("Failed to resubscribe to {}: {e}", self.name),
+                        }
+                    }
+                }
+                Either::Right((Some(item), _)) => return Some(item),
+                // The owner left, the matching `NameOwnerChanged` follows
+                Either::Right((None, _)) => self.stream = None,
+            }
+        }
+    }
+
+    async fn bind(&mut self) -> zbus::Result<BoxStream<'static, T>> {
+        let proxy = PlayerProxy::builder(&self.conn)
+            .destination(self.name.clone())?
+            .build().await?;
+        (self.subscribe)(proxy).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::pin;
+    use std::time::Duration;
+    use futures::future::{self, Either};
+    use futures::StreamExt;
+    use test_log::test;
+    use zbus::Connection;
+    use crate::runtime;
+    use crate::shared::{PlaybackStatus, OBJECT_PATH, PLAYER_INTERFACE};
+    use crate::sync::resilient::{ResilientEvent, ResilientPlayer};
+    use crate::test_util::serve_named_player;
+
+    const NAME: &str = "org.mpris.MediaPlayer2.zmpris_test.resilient";
+
+    #[test(tokio::test)]
+    async fn rebind_on_restart() -> anyhow::Result<()> {
+        let conn = Connection::session().await?;
+        let first = Connection::session().await?;
+        first.request_name(NAME).await?;
+
+        let mut player = ResilientPlayer::new(&conn, NAME).await?;
+        assert!(player.proxy().is_some());
+
+        first.release_name(NAME).await?;
+        assert_eq!(player.next_event().await, Some(ResilientEvent::Disconnected));
+        assert!(player.proxy().is_none());
+
+        let second = Connection::session().await?;
+        second.request_name(NAME).await?;
+        let owner = second.unique_name().expect("No unique name").to_owned();
+        assert_eq!(player.next_event().await, Some(ResilientEvent::Reconnected(owner)));
+        assert!(player.proxy().is_some());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn resubscribe_on_restart() -> anyhow::Result<()> {
+        let name = format!("{NAME}_streams");
+        let conn = Connection::session().await?;
+        let first = Connection::session().await?;
+        first.request_name(name.as_str()).await?;
+
+        let player = ResilientPlayer::new(&conn, &name).await?;
+        let mut seeked = player.receive_seeked().await?;
+        first.emit_signal(None::<()>, OBJECT_PATH, PLAYER_INTERFACE, "Seeked", &(1i64,)).await?;
+        assert_eq!(seeked.next().await.expect("No Seeked").args()?.position, 1);
+
+        first.release_name(name.as_str()).await?;
+        let second = Connection::session().await?;
+        second.request_name(name.as_str()).await?;
+        // Emitted until the stream is subscribed to the new owner
+        let emit = pin!(async {
+            loop {
+                second.emit_signal(None::<()>, OBJECT_PATH, PLAYER_INTERFACE, "Seeked", &(2i64,)).await?;
+                runtime::sleep(Duration::from_millis(50)).await;
+            }
+        });
+        let next = runtime::timeout(Duration::from_secs(5), future::select(seeked.next(), emit)).await?;
+        let signal = match next {
+            Either::Left((signal, _)) => signal.expect("No Seeked"),
+            Either::Right((result, _)) => return result,
+        };
+        assert_eq!(signal.args()?.position, 2);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn resubscribe_properties() -> anyhow::Result<()> {
+        let name = format!("{NAME}_properties");
+        let conn = Connection::session().await?;
+        let first = serve_named_player(&name, false).await?;
+
+        let player = ResilientPlayer::new(&conn, &name).await?;
+        let mut statuses = player.resubscribe(|proxy| async move { Ok(proxy.receive_playback_status_changed().await) }).await?;
+        let status = statuses.next().await.expect("No status").get().await?;
+        assert_eq!(status, PlaybackStatus::Stopped);
+
+        // The new owner doesn't emit `PropertiesChanged`, the value is read when subscribing
+        first.release_name(name.as_str()).await?;
+        let _second = serve_named_player(&name, true).await?;
+        let changed = runtime::timeout(Duration::from_secs(5), statuses.next()).await?.expect("No status");
+        assert_eq!(changed.get().await?, PlaybackStatus::Playing);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use zbus::names::InterfaceName;
+use zbus::proxy::CacheProperties;
+use zbus::{Connection, Proxy};
+use zvariant::OwnedValue;
+use crate::shared::{MediaPlayer2Properties, PlayerProperties, Snapshot};
+use crate::sync::discovery::properties;
+use crate::sync::{MediaPlayer2Proxy, PlayerProxy};
+
+/// Reads every property of the `org.mpris.MediaPlayer2` interface
+/// with a single `GetAll` call.
+pub async fn media_player(proxy: &MediaPlayer2Proxy<'_>) -> zbus::Result<MediaPlayer2Properties> {
+    MediaPlayer2Properties::try_from(get_all(proxy.inner()).await?)
+}
+
+/// Reads every property of the `org.mpris.MediaPlayer2.Player` interface
+/// with a single `GetAll` call.
+pub async fn player(proxy: &PlayerProxy<'_>) -> zbus::Result<PlayerProperties> {
+    PlayerProperties::try_from(get_all(proxy.inner()).await?)
+}
+
+/// Reads the state of both main interfaces of the player owning `destination`,
+/// issuing the two `GetAll` calls concurrently.
+///
+/// The proxies are built without property caching, so no additional
+/// round-trips or match rules are involved.
+pub async fn snapshot(conn: &Connection, destination: &str) -> zbus::Result<Snapshot> {
+    let media_player_proxy = MediaPlayer2Proxy::builder(conn)
+        .destination(destination)?
+        .cache_properties(CacheProperties::No)
+        .build().await?;
+    let player_proxy = PlayerProxy::builder(conn)
+        .destination(destination)?
+        .cache_properties(CacheProperties::No)
+        .build().await?;
+
+    let (media_player, player) = futures::try_join!(
+        media_player(&media_player_proxy),
+        player(&player_proxy),
+    )?;
+
+    Ok(Snapshot { media_player, player })
+}
+
+async fn get_all(proxy: &Proxy<'_>) -> zbus::Result<HashMap<String, OwnedValue>> {
+    let interface: InterfaceName<'_> = proxy.interface().as_ref();
+    Ok(properties(proxy).await?.get_all(interface).await?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use test_log::test;
+    use zbus::Connection;
+    use zvariant::Value;
+    use crate::server::state::MediaPlayer2State;
+    use crate::shared::PlaybackStatus;
+    use crate::test_util::serve_player;
+
+    #[test(tokio::test)]
+    async fn snapshot_player() -> anyhow::Result<()> {
+        let (server, player) = serve_player(MediaPlayer2State { identity: "zmpris".to_string(), ..Default::default() }).await?;
+        let track = HashMap::from([("xesam:title".to_string(), Value::from("Title").try_to_owned()?)]);
+        player.get_mut().await.update_backend(|engine| {
+            let mut events = engine.set_tracks(vec![track], Some(0));
+            events.extend(engine.play());
+            events
+        }).await?;
+
+        let conn = Connection::session().await?;
+        let snapshot = super::snapshot(&conn, server.unique_name().unwrap()).await?;
+        assert_eq!(snapshot.media_player.identity, "zmpris");
+        assert_eq!(snapshot.player.playback_status, PlaybackStatus::Playing);
+
+        Ok(())
+    }
+}
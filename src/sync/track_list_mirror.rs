@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use futures::stream::{self, Stream, StreamExt};
+use zbus::export::ordered_stream::{join, OrderedStreamExt};
+use zbus::fdo::{PropertiesChanged, PropertiesProxy};
+use zbus::names::InterfaceName;
+use zbus::Message;
+use zvariant::{OwnedObjectPath, OwnedValue, Value};
+use crate::shared::{track_id, TrackId, TrackIdExt, TRACK_LIST_INTERFACE};
+use crate::track_list::{TrackAdded, TrackListReplaced, TrackMetadataChanged, TrackRemoved};
+use crate::sync::tracks_metadata::{get_raw, DEFAULT_CHUNK_SIZE, DEFAULT_CONCURRENCY};
+use crate::sync::discovery::properties;
+use crate::sync::TrackListProxy;
+
+/// A track of a [`TrackListMirror`].
+#[derive(Debug, PartialEq)]
+pub struct Track {
+    pub id: OwnedObjectPath,
+    pub metadata: HashMap<String, OwnedValue>,
+}
+
+/// A change applied to a [`TrackListMirror`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackListChange {
+    /// A track was inserted at `index`.
+    Added { index: usize, id: OwnedObjectPath },
+    /// The track previously at `index` was removed.
+    Removed { index: usize, id: OwnedObjectPath },
+    /// Metadata of the track at `index` changed, `id` is its current id.
+    MetadataChanged { index: usize, id: OwnedObjectPath },
+    /// The whole tracklist was replaced or refetched.
+    Replaced,
+}
+
+/// A local copy of a player's tracklist.
+///
+/// The mirror fetches the tracklist with its metadata once and then keeps
+/// it in sync from the `TrackAdded`, `TrackRemoved`, `TrackListReplaced`
+/// and `TrackMetadataChanged` signals. As the `Tracks` property only
+/// invalidates, the track ids are refetched when it changes, unless the
+/// invalidation follows one of these signals.
+///
+/// Signals are applied while reading changes with
+/// [`next_change`](Self::next_change) or [`changes`](Self::changes).
+pub struct TrackListMirror {
+    proxy: TrackListProxy<'static>,
+    properties: PropertiesProxy<'static>,
+    tracks: Vec<Track>,
+    signals: Pin<Box<dyn Stream<Item = Message> + Send>>,
+    /// Whether the last signal changed the track ids, so the following
+    /// invalidation of `Tracks` is already applied.
+    ids_changed: bool,
+}
+
+impl TrackListMirror {
+    pub async fn new(proxy: TrackListProxy<'static>) -> zbus::Result<Self> {
+        let inner = proxy.inner();
+        let properties = properties(inner).await?;
+
+        let signals = join(
+            join(
+                join(
+                    proxy.receive_track_added().await?.into_inner(),
+                    proxy.receive_track_removed().await?.into_inner(),
+                ),
+                join(
+                    proxy.receive_track_list_replaced().await?.into_inner(),
+                    proxy.receive_track_metadata_changed().await?.into_inner(),
+                ),
+            ),
+            properties.receive_properties_changed_with_args(&[(0, TRACK_LIST_INTERFACE)]).await?.into_inner(),
+        ).into_stream();
+
+        let mut mirror = Self {
+            proxy,
+            properties,
+            tracks: Vec::new(),
+            signals: Box::pin(signals),
+            ids_changed: false,
+        };
+        mirror.refresh().await?;
+
+        Ok(mirror)
+    }
+
+    /// The mirrored tracks, in tracklist order.
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Index of the track with the given id.
+    pub fn position(&self, id: &TrackId<'_>) -> Option<usize> {
+        self.tracks.iter().position(|it| it.id.as_ref() == *id)
+    }
+
+    /// Waits for the next change of the tracklist and applies it to the mirror.
+    ///
+    /// Returns [`None`] when the signal streams end, e.g. the connection is closed.
+    pub async fn next_change(&mut self) -> Option<zbus::Result<TrackListChange>> {
+        loop {
+            let message = self.signals.next().await?;
+            match self.apply(message).await {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Stream of the changes of the tracklist, see [`next_change`](Self::next_change).
+    pub fn changes(&mut self) -> impl Stream<Item = zbus::Result<TrackListChange>> + Unpin + '_ {
+        Box::pin(stream::unfold(self, |mirror| async move {
+            let change = mirror.next_change().await?;
+            Some((change, mirror))
+        }))
+    }
+
+    /// Refetches the track ids, and the metadata of tracks not mirrored yet.
+    ///
+    /// Returns [`TrackListChange::Replaced`] if the tracklist differs from the mirror.
+    pub async fn refresh(&mut self) -> zbus::Result<Option<TrackListChange>> {
+        let interface = InterfaceName::from_static_str_unchecked(TRACK_LIST_INTERFACE);
+        let ids = Vec::<OwnedObjectPath>::try_from(self.properties.get(interface, "Tracks").await?)?;
+        if self.tracks.iter().map(|it| &it.id).eq(ids.iter()) {
+            return Ok(None);
+        }
+
+        self.replace(ids).await?;
+        Ok(Some(TrackListChange::Replaced))
+    }
+
+    async fn apply(&mut self, message: Message) -> zbus::Result<Option<TrackListChange>> {
+        let ids_changed = std::mem::take(&mut self.ids_changed);
+
+        if let Some(signal) = TrackAdded::from_message(message.clone()) {
+            let args = signal.args()?;
+            let metadata = to_owned_metadata(args.metadata());
+            let Some(id) = track_id(&metadata).filter(|it| self.position(it).is_none()) else {
+                return self.refresh().await;
+            };
+            let index = if args.after_track().is_no_track() {
+                0
+            } else {
+                match self.position(args.after_track()) {
+                    Some(index) => index + 1,
+                    None => return self.refresh().await,
+                }
+            };
+            self.tracks.insert(index, Track { id: id.clone(), metadata });
+            self.ids_changed = true;
+            return Ok(Some(TrackListChange::Added { index, id }));
+        }
+
+        if let Some(signal) = TrackRemoved::from_message(message.clone()) {
+            let args = signal.args()?;
+            let Some(index) = self.position(args.track_id()) else {
+                return Ok(None);
+            };
+            let track = self.tracks.remove(index);
+            self.ids_changed = true;
+            return Ok(Some(TrackListChange::Removed { index, id: track.id }));
+        }
+
+        if let Some(signal) = TrackListReplaced::from_message(message.clone()) {
+            let args = signal.args()?;
+            let ids = args.tracks().iter().map(|it| it.to_owned().into()).collect();
+            self.replace(ids).await?;
+            self.ids_changed = true;
+            return Ok(Some(TrackListChange::Replaced));
+        }
+
+        if let Some(signal) = TrackMetadataChanged::from_message(message.clone()) {
+            let args = signal.args()?;
+            let Some(index) = self.position(args.track_id()) else {
+                return Ok(None);
+            };
+            let metadata = to_owned_metadata(args.metadata());
+            let track = &mut self.tracks[index];
+            // The signal carries the old id if the track id has changed
+            if let Some(id) = track_id(&metadata) {
+                track.id = id;
+            }
+            track.metadata = metadata;
+            return Ok(Some(TrackListChange::MetadataChanged { index, id: track.id.clone() }));
+        }
+
+        if let Some(signal) = PropertiesChanged::from_message(message) {
+            let args = signal.args()?;
+            let changed = args.changed_properties().contains_key("Tracks")
+                || args.invalidated_properties().contains(&"Tracks");
+            if changed && !ids_changed {
+                return self.refresh().await;
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn replace(&mut self, ids: Vec<OwnedObjectPath>) -> zbus::Result<()> {
+        let mut known: HashMap<OwnedObjectPath, HashMap<String, OwnedValue>> = self.tracks
+            .drain(..)
+            .map(|it| (it.id, it.metadata))
+            .collect();
+
+        let missing: Vec<OwnedObjectPath> = ids.iter()
+            .filter(|it| !known.contains_key(*it))
+            .cloned()
+            .collect();
+        known.extend(get_raw(&self.proxy, &missing, DEFAULT_CHUNK_SIZE, DEFAULT_CONCURRENCY).await?);
+
+        self.tracks = ids.into_iter()
+            .map(|id| {
+                let metadata = known.remove(&id).unwrap_or_default();
+                Track { id, metadata }
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+fn to_owned_metadata(metadata: &HashMap<&str, Value<'_>>) -> HashMap<String, OwnedValue> {
+    metadata.iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.try_to_owned().ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use futures::stream::StreamExt;
+    use test_log::test;
+    use zvariant::{ObjectPath, OwnedValue, Value};
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::shared::NO_TRACK;
+    use crate::sync::track_list_mirror::{TrackListChange, TrackListMirror};
+    use crate::test_util::{proxy, serve};
+
+    fn title(title: &str) -> HashMap<String, OwnedValue> {
+        HashMap::from([("xesam:title".to_string(), Value::from(title).try_to_owned().unwrap())])
+    }
+
+    #[test(tokio::test)]
+    async fn mirror_signals() -> anyhow::Result<()> {
+        let (server, iface) = serve(TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let emitter = iface.signal_emitter();
+        let ids = iface.get_mut().await.replace(emitter, vec![title("a"), title("b")], None).await?;
+
+        let mut mirror = TrackListMirror::new(proxy(&server).await?).await?;
+        assert_eq!(mirror.tracks().len(), 2);
+
+        let added = iface.get_mut().await.add(emitter, title("c"), &ids[0], false).await?;
+        assert_eq!(mirror.next_change().await.transpose()?, Some(TrackListChange::Added { index: 1, id: added.clone() }));
+
+        iface.get_mut().await.remove(emitter, &ids[0]).await?;
+        assert_eq!(mirror.next_change().await.transpose()?, Some(TrackListChange::Removed { index: 0, id: ids[0].clone() }));
+
+        iface.get_mut().await.set_metadata(emitter, &ids[1], title("B")).await?;
+        assert_eq!(mirror.next_change().await.transpose()?, Some(TrackListChange::MetadataChanged { index: 1, id: ids[1].clone() }));
+
+        let no_track = ObjectPath::from_static_str_unchecked(NO_TRACK);
+        let added = iface.get_mut().await.add(emitter, title("d"), &no_track, false).await?;
+        assert_eq!(mirror.changes().next().await.transpose()?, Some(TrackListChange::Added { index: 0, id: added.clone() }));
+
+        let ids: Vec<_> = mirror.tracks().iter().map(|it| it.id.clone()).collect();
+        let served: Vec<_> = iface.get().await.track_list().iter().map(|it| it.id.clone()).collect();
+        assert_eq!(ids, served);
+        assert_eq!(mirror.tracks()[2].metadata["xesam:title"], Value::from("B").try_to_owned()?);
+
+        iface.get_mut().await.replace(emitter, vec![title("e")], Some(0)).await?;
+        assert_eq!(mirror.next_change().await.transpose()?, Some(TrackListChange::Replaced));
+        assert_eq!(mirror.tracks()[0].metadata["xesam:title"], Value::from("e").try_to_owned()?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::Write;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use crate::playlist_file::{self, PlaylistEntry, PlaylistFormat};
+use crate::runtime;
+use crate::sync::queue::enqueue;
+use crate::sync::tracks_metadata::{get_tracks, get_tracks_metadata};
+use crate::sync::{MediaPlayer2Proxy, PlayerProxy, TrackListProxy};
+
+/// Writes the player's tracklist to `writer` in the given format.
+///
+/// Titles, artists and durations come from the track metadata, tracks
+/// without a `xesam:url` are skipped.
+///
+/// ## Returns
+/// The number of written entries.
+pub async fn export(track_list: &TrackListProxy<'_>, format: PlaylistFormat, writer: impl Write) -> Result<usize> {
+    let ids = get_tracks(track_list).await?;
+    let entries: Vec<PlaylistEntry> = get_tracks_metadata(track_list, &ids).await?
+        .iter()
+        .filter_map(|(_, metadata)| PlaylistEntry::from_metadata(metadata))
+        .collect();
+
+    playlist_file::write(format, &entries, writer)?;
+    Ok(entries.len())
+}
+
+/// Writes the player's tracklist to `path`, guessing the format from its extension.
+///
+/// See [`export`].
+pub async fn export_to_file(track_list: &TrackListProxy<'_>, path: &Path) -> Result<usize> {
+    let format = PlaylistFormat::from_path(path)
+        .ok_or(anyhow!("Unknown playlist format: {}", path.display()))?;
+    let mut output = Vec::new();
+    let count = export(track_list, format, &mut output).await?;
+    let path = path.to_path_buf();
+    runtime::unblock(move || std::fs::write(path, output)).await?;
+    Ok(count)
+}
+
+/// Loads `entries` into the player.
+///
+/// Entries whose URI scheme is not in `SupportedUriSchemes` are skipped.
+/// They are appended to the tracklist if the player has one that can be
+/// edited, otherwise they are opened one after another with `OpenUri`.
+///
+/// ## Returns
+/// The number of loaded entries.
+pub async fn import(media_player: &MediaPlayer2Proxy<'_>, entries: &[PlaylistEntry]) -> Result<usize> {
+    let schemes = media_player.supported_uri_schemes().await?;
+    let uris: Vec<&str> = entries.iter()
+        .map(|it| it.location.as_str())
+        .filter(|uri| match playlist_file::scheme(uri) {
+            Some(scheme) => schemes.iter().any(|it| it.eq_ignore_ascii_case(scheme)),
+            None => false,
+        })
+        .collect();
+    if uris.len() < entries.len() {
+        log::debug!("Skipping {} entries with unsupported URI schemes", entries.len() - uris.len());
+    }
+    if uris.is_empty() {
+        return Ok(0);
+    }
+
+    let inner = media_player.inner();
+    if media_player.has_track_list().await? {
+        let track_list = TrackListProxy::builder(inner.connection())
+            .destination(inner.destination().to_owned())?
+            .build().await?;
+        if track_list.can_edit_tracks().await? {
+            enqueue(&track_list, &uris).await?;
+            return Ok(uris.len());
+        }
+    }
+
+    let player = PlayerProxy::builder(inner.connection())
+        .destination(inner.destination().to_owned())?
+        .build().await?;
+    for uri in &uris {
+        player.open_uri(uri).await?;
+    }
+    Ok(uris.len())
+}
+
+/// Reads the playlist file at `path` and loads its entries into the player.
+///
+/// See [`import`] and [`playlist_file::parse_file`].
+pub async fn import_file(media_player: &MediaPlayer2Proxy<'_>, path: &Path) -> Result<usize> {
+    let path = path.to_path_buf();
+    let entries = runtime::unblock(move || playlist_file::parse_file(&path)).await?;
+    import(media_player, &entries).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use test_log::test;
+    use zvariant::Value;
+    use crate::playlist_file::{PlaylistEntry, PlaylistFormat};
+    use crate::server::state::MediaPlayer2State;
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::shared::{Metadata, OBJECT_PATH};
+    use crate::sync::tracklist_file::{export, import};
+    use crate::test_util::{proxy, serve, serve_player};
+
+    fn entry(location: &str) -> PlaylistEntry {
+        PlaylistEntry { location: location.to_string(), ..Default::default() }
+    }
+
+    #[test(tokio::test)]
+    async fn import_without_track_list() -> anyhow::Result<()> {
+        let (server, player) = serve_player(MediaPlayer2State {
+            supported_uri_schemes: vec!["file".to_string(), "http".to_string()],
+            ..Default::default()
+        }).await?;
+
+        let entries = [entry("file:///a.mp3"), entry("rtsp://example.com/b"), entry("HTTP://example.com/c")];
+        assert_eq!(import(&proxy(&server).await?, &entries).await?, 2);
+        let opened: Vec<String> = player.get().await.backend().tracks().iter()
+            .filter_map(|it| Metadata::from(it).url)
+            .collect();
+        assert_eq!(opened, ["file:///a.mp3", "HTTP://example.com/c"]);
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn import_into_track_list() -> anyhow::Result<()> {
+        let (server, player) = serve_player(MediaPlayer2State {
+            has_track_list: true,
+            supported_uri_schemes: vec!["file".to_string()],
+            ..Default::default()
+        }).await?;
+        server.object_server().at(OBJECT_PATH, TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let tracks = server.object_server().interface::<_, TrackListServer>(OBJECT_PATH).await?;
+
+        let entries = [entry("file:///a.mp3"), entry("http://example.com/b"), entry("file:///c.mp3")];
+        assert_eq!(import(&proxy(&server).await?, &entries).await?, 2);
+        let queued: Vec<String> = tracks.get().await.track_list().iter()
+            .filter_map(|it| Metadata::from(&it.metadata).url)
+            .collect();
+        assert_eq!(queued, ["file:///a.mp3", "file:///c.mp3"]);
+        // Appended rather than opened
+        assert!(player.get().await.backend().tracks().is_empty());
+
+        anyhow::Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn export_track_list() -> anyhow::Result<()> {
+        let (server, iface) = serve(TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let tracks = vec![
+            HashMap::from([
+                ("xesam:url".to_string(), Value::from("file:///a.mp3").try_to_owned()?),
+                ("xesam:title".to_string(), Value::from("First").try_to_owned()?),
+                ("mpris:length".to_string(), Value::from(61_000_000i64).try_to_owned()?),
+            ]),
+            // Skipped, there's nothing to play
+            HashMap::from([("xesam:title".to_string(), Value::from("No URL").try_to_owned()?)]),
+            HashMap::from([("xesam:url".to_string(), Value::from("http://example.com/b").try_to_owned()?)]),
+        ];
+        iface.get_mut().await.replace(iface.signal_emitter(), tracks, None).await?;
+
+        let mut output = Vec::new();
+        assert_eq!(export(&proxy(&server).await?, PlaylistFormat::M3u, &mut output).await?, 2);
+        assert_eq!(String::from_utf8(output)?, "#EXTM3U\n\
+            #EXTINF:61,First\n\
+            file:///a.mp3\n\
+            #EXTINF:-1,\n\
+            http://example.com/b\n");
+
+        anyhow::Ok(())
+    }
+}
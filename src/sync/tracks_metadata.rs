@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use futures::stream::{StreamExt, TryStreamExt};
+use zbus::names::InterfaceName;
+use zvariant::{OwnedObjectPath, OwnedValue};
+use crate::shared::{track_id, Metadata, TrackId};
+use crate::sync::discovery::properties;
+use crate::sync::TrackListProxy;
+
+/// Number of track ids sent in a single `GetTracksMetadata` call by [`get_tracks_metadata`].
+pub const DEFAULT_CHUNK_SIZE: usize = 100;
+
+/// Number of `GetTracksMetadata` calls [`get_tracks_metadata`] keeps in flight.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Fetches the metadata of `ids` with [`DEFAULT_CHUNK_SIZE`] and [`DEFAULT_CONCURRENCY`].
+///
+/// See [`get_tracks_metadata_chunked`].
+pub async fn get_tracks_metadata(
+    proxy: &TrackListProxy<'_>,
+    ids: &[OwnedObjectPath],
+) -> zbus::Result<Vec<(OwnedObjectPath, Metadata)>> {
+    get_tracks_metadata_chunked(proxy, ids, DEFAULT_CHUNK_SIZE, DEFAULT_CONCURRENCY).await
+}
+
+/// Fetches the metadata of `ids`, sending at most `chunk_size` ids per
+/// `GetTracksMetadata` call and keeping at most `concurrency` calls in flight.
+///
+/// Results are returned in the order of `ids`. Tracks the player returned
+/// no metadata for (e.g. because they are no longer in the tracklist) are
+/// skipped.
+pub async fn get_tracks_metadata_chunked(
+    proxy: &TrackListProxy<'_>,
+    ids: &[OwnedObjectPath],
+    chunk_size: usize,
+    concurrency: usize,
+) -> zbus::Result<Vec<(OwnedObjectPath, Metadata)>> {
+    let mut metadata = get_raw(proxy, ids, chunk_size, concurrency).await?;
+    Ok(ids.iter()
+        .filter_map(|id| {
+            let metadata = metadata.remove(id)?;
+            Some((id.clone(), Metadata::from(&metadata)))
+        })
+        .collect())
+}
+
+/// Chunked `GetTracksMetadata` returning the untyped metadata maps by track id.
+pub(crate) async fn get_raw(
+    proxy: &TrackListProxy<'_>,
+    ids: &[OwnedObjectPath],
+    chunk_size: usize,
+    concurrency: usize,
+) -> zbus::Result<HashMap<OwnedObjectPath, HashMap<String, OwnedValue>>> {
+    futures::stream::iter(ids.chunks(chunk_size.max(1)))
+        .map(|chunk| async move {
+            let track_ids: Vec<&TrackId<'_>> = chunk.iter().map(|it| &**it).collect();
+            let reply = proxy.get_tracks_metadata(&track_ids).await?;
+            zbus::Result::Ok(assign(chunk, reply))
+        })
+        .buffered(concurrency.max(1))
+        .try_fold(HashMap::new(), |mut all, chunk| async move {
+            all.extend(chunk);
+            Ok(all)
+        })
+        .await
+}
+
+/// Reads `Tracks` bypassing the proxy cache, which lags behind our own edits.
+pub(crate) async fn get_tracks(track_list: &TrackListProxy<'_>) -> zbus::Result<Vec<OwnedObjectPath>> {
+    let inner = track_list.inner();
+    let properties = properties(inner).await?;
+    let interface: InterfaceName<'_> = inner.interface().as_ref();
+    Ok(Vec::<OwnedObjectPath>::try_from(properties.get(interface, "Tracks").await?)?)
+}
+
+fn assign(
+    chunk: &[OwnedObjectPath],
+    reply: Vec<HashMap<String, OwnedValue>>,
+) -> Vec<(OwnedObjectPath, HashMap<String, OwnedValue>)> {
+    // Players must include `mpris:trackid`, fall back to the request order for those that don't
+    if reply.len() == chunk.len() && reply.iter().all(|it| track_id(it).is_none()) {
+        return chunk.iter().cloned().zip(reply).collect();
+    }
+    reply.into_iter()
+        .filter_map(|metadata| Some((track_id(&metadata)?, metadata)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use test_log::test;
+    use zvariant::{ObjectPath, OwnedObjectPath, Value};
+    use crate::server::track_list::{TrackListServer, DEFAULT_ID_BASE};
+    use crate::sync::tracks_metadata::get_tracks_metadata_chunked;
+    use crate::test_util::{proxy, serve};
+
+    #[test(tokio::test)]
+    async fn chunked_metadata() -> anyhow::Result<()> {
+        let (server, iface) = serve(TrackListServer::new(DEFAULT_ID_BASE)?).await?;
+        let tracks = (0..5)
+            .map(|it| HashMap::from([("xesam:title".to_string(), Value::from(format!("Track {it}")).try_to_owned().unwrap())]))
+            .collect();
+        let mut ids = iface.get_mut().await.replace(iface.signal_emitter(), tracks, None).await?;
+        ids.insert(2, OwnedObjectPath::from(ObjectPath::try_from("/zmpris/track/unknown")?));
+
+        let result = get_tracks_metadata_chunked(&proxy(&server).await?, &ids, 2, 2).await?;
+        assert_eq!(result.len(), 5);
+        for (index, (id, metadata)) in result.into_iter().enumerate() {
+            assert_eq!(metadata.track_id.as_ref(), Some(&id));
+            assert_eq!(metadata.title, Some(format!("Track {index}")));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::channel::mpsc;
+use futures::stream::{Stream, StreamExt};
+use zbus::names::{BusName, OwnedUniqueName};
+use zbus::{Connection, Task};
+use crate::shared::PlayerEvent;
+use crate::watcher::{self, Handlers};
+
+/// Watches every media player on the bus through a single set of match rules.
+///
+/// Instead of every proxy installing its own match rules, the watcher
+/// installs one for `PropertiesChanged` on the Player interface, one for
+/// `Seeked` and one for `NameOwnerChanged` of MPRIS names, and dispatches
+/// incoming signals by sender to the streams returned from
+/// [`subscribe`](Self::subscribe).
+///
+/// Dispatching stops when the watcher is dropped.
+pub struct Watcher {
+    conn: Connection,
+    handlers: Handlers,
+    _task: Task<()>,
+}
+
+impl Watcher {
+    pub async fn new(conn: &Connection) -> zbus::Result<Self> {
+        let messages = watcher::signals(conn).await?;
+        let handlers = Handlers::default();
+        let names = watcher::name_counts(conn).await?;
+        let task = conn.executor().spawn(watcher::dispatch(messages, handlers.clone(), names), "zmpris watcher");
+
+        Ok(Self {
+            conn: conn.clone(),
+            handlers,
+            _task: task,
+        })
+    }
+
+    /// Returns a stream of events emitted by the player owning `name`.
+    ///
+    /// Well-known names are resolved to their current owner, so the stream
+    /// ends once that owner holds no MPRIS name anymore (e.g. the player exits).
+    pub async fn subscribe<'n, N>(&self, name: N) -> zbus::Result<PlayerEvents>
+    where
+        N: TryInto<BusName<'n>>,
+        N::Error: Into<zbus::Error>,
+    {
+        let (owner, receiver) = watcher::subscribe(&self.conn, &self.handlers, name.try_into().map_err(Into::into)?).await?;
+        Ok(PlayerEvents { owner, receiver })
+    }
+}
+
+/// Stream of [`PlayerEvent`]s of a single player, see [`Watcher::subscribe`].
+#[derive(Debug)]
+pub struct PlayerEvents {
+    owner: OwnedUniqueName,
+    receiver: mpsc::UnboundedReceiver<PlayerEvent>,
+}
+
+impl PlayerEvents {
+    /// Unique name of the player the events come from.
+    pub fn owner(&self) -> &OwnedUniqueName {
+        &self.owner
+    }
+}
+
+impl Stream for PlayerEvents {
+    type Item = PlayerEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use futures::StreamExt;
+    use test_log::test;
+    use zbus::Connection;
+    use zvariant::Value;
+    use crate::shared::{PlayerEvent, BASE_PATH, OBJECT_PATH, PLAYER_INTERFACE};
+    use crate::sync::watcher::Watcher;
+
+    #[test(tokio::test)]
+    async fn subscribe_missing_player() -> anyhow::Result<()> {
+        let conn = Connection::session().await?;
+        let watcher = Watcher::new(&conn).await?;
+
+        let result = watcher.subscribe("org.mpris.MediaPlayer2.com.example.application").await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn dispatch_by_sender() -> anyhow::Result<()> {
+        let conn = Connection::session().await?;
+        let watcher = Watcher::new(&conn).await?;
+        let unique_name = conn.unique_name().expect("No unique name").to_owned();
+        let mut events = watcher.subscribe(unique_name).await?;
+
+        let changed = HashMap::from([("Volume", Value::from(0.5))]);
+        conn.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+        ).await?;
+        conn.emit_signal(None::<()>, OBJECT_PATH, PLAYER_INTERFACE, "Seeked", &(42i64,)).await?;
+
+        let Some(PlayerEvent::PropertiesChanged { changed, .. }) = events.next().await else {
+            anyhow::bail!("Expected PropertiesChanged");
+        };
+        assert_eq!(f64::try_from(&changed["Volume"])?, 0.5);
+        assert_eq!(events.next().await, Some(PlayerEvent::Seeked(42)));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn end_with_last_name() -> anyhow::Result<()> {
+        let first = format!("{BASE_PATH}zmpris_test.watcher");
+        let instance = format!("{first}.instance{}", std::process::id());
+        let conn = Connection::session().await?;
+        conn.request_name(first.as_str()).await?;
+        conn.request_name(instance.as_str()).await?;
+        let watcher = Watcher::new(&conn).await?;
+        let mut events = watcher.subscribe(first.as_str()).await?;
+
+        // Still owns the instance name
+        conn.release_name(first.as_str()).await?;
+        conn.emit_signal(None::<()>, OBJECT_PATH, PLAYER_INTERFACE, "Seeked", &(42i64,)).await?;
+        assert_eq!(events.next().await, Some(PlayerEvent::Seeked(42)));
+
+        conn.release_name(instance.as_str()).await?;
+        assert_eq!(events.next().await, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use zbus::object_server::{Interface, InterfaceRef};
+use zbus::proxy::{self, CacheProperties};
+use zbus::{Connection, Proxy};
+use zvariant::OwnedValue;
+use crate::server::engine::PlaybackEngine;
+use crate::server::media_player::MediaPlayer2Server;
+use crate::server::player::PlayerServer;
+use crate::server::state::{MediaPlayer2State, PlayerState, StateStore};
+use crate::shared::OBJECT_PATH;
+
+/// Serves `iface` at [`OBJECT_PATH`] on a new session connection.
+pub(crate) async fn serve<I: Interface>(iface: I) -> zbus::Result<(Connection, InterfaceRef<I>)> {
+    let server = zbus::connection::Builder::session()?
+        .serve_at(OBJECT_PATH, iface)?
+        .build().await?;
+    let iface = server.object_server().interface(OBJECT_PATH).await?;
+    Ok((server, iface))
+}
+
+/// Serves a [`PlayerServer`] and its root interface on a new session connection.
+pub(crate) async fn serve_player(media_player: MediaPlayer2State) -> zbus::Result<(Connection, InterfaceRef<PlayerServer>)> {
+    let server = Connection::session().await?;
+    let store = StateStore::new(&server, media_player, PlayerState::default())?;
+    let player = MediaPlayer2Server::serve(&server, PlayerServer::new(PlaybackEngine::default(), store)).await?;
+    player.get_mut().await.update_backend(|_| Vec::new()).await?;
+    Ok((server, player))
+}
+
+/// Serves a player owning `name`, with `name` as its identity, and
+/// starts playing a track if `playing`.
+pub(crate) async fn serve_named_player(name: &str, playing: bool) -> zbus::Result<Connection> {
+    let (server, player) = serve_player(MediaPlayer2State { identity: name.to_string(), ..Default::default() }).await?;
+    if playing {
+        let track = HashMap::from([("xesam:title".to_string(), OwnedValue::from(zvariant::Str::from("Title")))]);
+        player.get_mut().await.update_backend(|engine| {
+            let mut events = engine.set_tracks(vec![track], Some(0));
+            events.extend(engine.play());
+            events
+        }).await?;
+    }
+    server.request_name(name).await?;
+    Ok(server)
+}
+
+/// Builds a proxy on a new connection for the objects served by `server`,
+/// without caching properties.
+pub(crate) async fn proxy<P>(server: &Connection) -> zbus::Result<P>
+where
+    P: From<Proxy<'static>> + proxy::Defaults,
+{
+    let conn = Connection::session().await?;
+    proxy::Builder::new(&conn)
+        .destination(server.unique_name().expect("No unique name").to_owned())?
+        .cache_properties(CacheProperties::No)
+        .build().await
+}
+
+/// Creates an empty directory named after `test` holding `files`, as
+/// pairs of file name and content.
+pub(crate) fn playlist_dir(test: &str, files: &[(&str, &str)]) -> std::io::Result<PathBuf> {
+    let directory = std::env::temp_dir().join(format!("zmpris-{test}-{}", std::process::id()));
+    if directory.exists() {
+        std::fs::remove_dir_all(&directory)?;
+    }
+    std::fs::create_dir_all(&directory)?;
+    for (name, content) in files {
+        std::fs::write(directory.join(name), content)?;
+    }
+    Ok(directory)
+}
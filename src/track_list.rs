@@ -63,5 +63,5 @@ pub trait TrackList {
 
     /// Tracks property
     #[zbus(property(emits_changed_signal = "invalidates"))]
-    fn tracks(&self) -> zbus::Result<Vec<TrackId>>;
+    fn tracks(&self) -> zbus::Result<Vec<TrackId<'_>>>;
 }
@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+/// The scheme of `uri`, e.g. `file` or `http`, [`None`] if it's not a URI.
+pub fn scheme(uri: &str) -> Option<&str> {
+    let (scheme, _) = uri.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next()?.is_ascii_alphabetic()
+        && chars.all(|it| it.is_ascii_alphanumeric() || matches!(it, '+' | '-' | '.'))
+        // A single letter is a Windows drive rather than a scheme
+        && scheme.len() > 1;
+    valid.then_some(scheme)
+}
+
+/// The `file://` URI of `path`, with everything but unreserved characters
+/// and `/` percent-encoded.
+pub(crate) fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// The local path of a `file://` URI.
+///
+/// [`None`] for other schemes and for files on other hosts, the host has to
+/// be empty or `localhost`.
+pub(crate) fn file_path(uri: &str) -> Option<PathBuf> {
+    if !scheme(uri)?.eq_ignore_ascii_case("file") {
+        return None;
+    }
+    let rest = uri.split_once(':')?.1.strip_prefix("//")?;
+    let (host, path) = rest.split_at(rest.find('/')?);
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return None;
+    }
+    Some(PathBuf::from(String::from_utf8(percent_decode(path)).ok()?))
+}
+
+/// Decodes a `data:[<mediatype>][;base64],<data>` URI into its media type and data.
+pub(crate) fn decode_data_uri(uri: &str) -> Option<(Option<String>, Vec<u8>)> {
+    if !scheme(uri)?.eq_ignore_ascii_case("data") {
+        return None;
+    }
+    let (header, data) = uri.split_once(':')?.1.split_once(',')?;
+    let (mime_type, base64) = match header.strip_suffix(";base64") {
+        Some(mime_type) => (mime_type, true),
+        None => (header, false),
+    };
+    let mime_type = mime_type.split(';').next().filter(|it| !it.is_empty()).map(String::from);
+    let data = match base64 {
+        true => decode_base64(&String::from_utf8(percent_decode(data)).ok()?)?,
+        false => percent_decode(data),
+    };
+    Some((mime_type, data))
+}
+
+pub(crate) fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes.get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|it| u8::from_str_radix(std::str::from_utf8(it).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                output.push(byte);
+                index += 3;
+            }
+            None => {
+                output.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    output
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in input.bytes().filter(|it| !it.is_ascii_whitespace() && *it != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use crate::uri::{decode_data_uri, file_path, file_uri};
+    use test_log::test;
+
+    #[test]
+    fn file_uris() {
+        let path = Path::new("/music/cover art.jpg");
+        assert_eq!(file_uri(path), "file:///music/cover%20art.jpg");
+        assert_eq!(file_path(&file_uri(path)), Some(path.to_path_buf()));
+        assert_eq!(file_path("FILE://localhost/a%25b"), Some(PathBuf::from("/a%b")));
+        assert_eq!(file_path("file://example.com/a.jpg"), None);
+        assert_eq!(file_path("http:///a.jpg"), None);
+    }
+
+    #[test]
+    fn data_uris() {
+        assert_eq!(
+            decode_data_uri("data:image/png;base64,aGVsbG8gd29ybGQ="),
+            Some((Some("image/png".to_string()), b"hello world".to_vec())),
+        );
+        assert_eq!(decode_data_uri("data:,a%20b"), Some((None, b"a b".to_vec())));
+        assert_eq!(decode_data_uri("data:;base64,!"), None);
+    }
+}
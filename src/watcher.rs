@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use futures::channel::mpsc;
+use futures::stream::{Stream, StreamExt};
+use zbus::export::ordered_stream::{join, OrderedStreamExt};
+use zbus::fdo::{DBusProxy, NameOwnerChanged};
+use zbus::message::Type;
+use zbus::names::{BusName, OwnedUniqueName};
+use zbus::{Connection, MatchRule, Message, MessageStream};
+use crate::shared::{PlayerEvent, BASE_PATH, OBJECT_PATH, PLAYER_INTERFACE, PROPERTIES_INTERFACE};
+
+/// Senders of the subscribed event streams by player.
+pub(crate) type Handlers = Arc<Mutex<HashMap<OwnedUniqueName, Vec<mpsc::UnboundedSender<PlayerEvent>>>>>;
+
+/// The signals the watchers dispatch: `PropertiesChanged` on the Player
+/// interface, `Seeked` and `NameOwnerChanged` of MPRIS names.
+///
+/// The match rules are removed when the stream is dropped.
+pub(crate) async fn signals(conn: &Connection) -> zbus::Result<impl Stream<Item = zbus::Result<Message>> + Unpin + Send + 'static> {
+    let properties_changed = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(PROPERTIES_INTERFACE)?
+        .member("PropertiesChanged")?
+        .path(OBJECT_PATH)?
+        .arg(0, PLAYER_INTERFACE)?
+        .build();
+    let seeked = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(PLAYER_INTERFACE)?
+        .member("Seeked")?
+        .path(OBJECT_PATH)?
+        .build();
+    let name_owner_changed = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg0ns(BASE_PATH.trim_end_matches('.'))?
+        .build();
+
+    // Joined as ordered streams so signals are dispatched in the order they arrived
+    Ok(join(
+        join(
+            MessageStream::for_match_rule(properties_changed, conn, None).await?,
+            MessageStream::for_match_rule(seeked, conn, None).await?,
+        ),
+        MessageStream::for_match_rule(name_owner_changed, conn, None).await?,
+    ).into_stream())
+}
+
+/// Adds a subscriber for the events of the player owning `name`, returning
+/// the owner and the receiving end.
+pub(crate) async fn subscribe(
+    conn: &Connection,
+    handlers: &Handlers,
+    name: BusName<'_>,
+) -> zbus::Result<(OwnedUniqueName, mpsc::UnboundedReceiver<PlayerEvent>)> {
+    let owner = match name {
+        BusName::Unique(name) => OwnedUniqueName::from(name.into_owned()),
+        BusName::WellKnown(name) => {
+            let dbus = DBusProxy::new(conn).await?;
+            dbus.get_name_owner(BusName::WellKnown(name)).await?
+        }
+    };
+
+    let (sender, receiver) = mpsc::unbounded();
+    handlers.lock()
+        .expect("Watcher handlers lock poisoned")
+        .entry(owner.clone())
+        .or_default()
+        .push(sender);
+
+    Ok((owner, receiver))
+}
+
+/// Number of MPRIS names owned by each player on the bus.
+///
+/// Taken after installing the match rules of [`signals`], which keep it up
+/// to date in [`dispatch`].
+pub(crate) async fn name_counts(conn: &Connection) -> zbus::Result<HashMap<OwnedUniqueName, usize>> {
+    let dbus = DBusProxy::new(conn).await?;
+    let mut counts = HashMap::new();
+    for name in dbus.list_names().await? {
+        if !name.starts_with(BASE_PATH) {
+            continue;
+        }
+        if let Ok(owner) = dbus.get_name_owner(name.inner().clone()).await {
+            *counts.entry(owner).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Sends the `messages` to the subscribers of their sender, and ends the
+/// subscriptions of players once they give up their last MPRIS name.
+///
+/// `names` are the counts from [`name_counts`].
+pub(crate) async fn dispatch(
+    mut messages: impl Stream<Item = zbus::Result<Message>> + Unpin,
+    handlers: Handlers,
+    mut names: HashMap<OwnedUniqueName, usize>,
+) {
+    while let Some(message) = messages.next().await {
+        let Ok(message) = message else {
+            continue;
+        };
+
+        if let Some(signal) = NameOwnerChanged::from_message(message.clone()) {
+            let Ok(args) = signal.args() else {
+                continue;
+            };
+            if !args.name().starts_with(BASE_PATH) {
+                continue;
+            }
+            if let Some(new_owner) = args.new_owner().as_ref() {
+                *names.entry(OwnedUniqueName::from(new_owner.to_owned())).or_default() += 1;
+            }
+            // Players may own several MPRIS names, e.g. an instance name
+            if let Some(old_owner) = args.old_owner().as_ref().map(|it| OwnedUniqueName::from(it.to_owned())) {
+                let count = names.entry(old_owner.clone()).or_default();
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    names.remove(&old_owner);
+                    handlers.lock()
+                        .expect("Watcher handlers lock poisoned")
+                        .remove(&old_owner);
+                }
+            }
+            continue;
+        }
+
+        let Some(sender) = message.header().sender().map(|it| OwnedUniqueName::from(it.to_owned())) else {
+            continue;
+        };
+        let mut handlers = handlers.lock().expect("Watcher handlers lock poisoned");
+        let Some(senders) = handlers.get_mut(&sender) else {
+            continue;
+        };
+        // `PlayerEvent` can't be cloned, so the message is decoded for every subscriber
+        senders.retain(|it| match PlayerEvent::from_message(&message) {
+            Some(event) => it.unbounded_send(event).is_ok(),
+            None => !it.is_closed(),
+        });
+        if senders.is_empty() {
+            handlers.remove(&sender);
+        }
+    }
+}